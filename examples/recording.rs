@@ -25,6 +25,8 @@ fn main() {
     while window.render() {
         c.prepend_to_local_rotation(&Vec3::new(0.0f32, 0.014, 0.0));
 
-        recorder.snap(&mut window);
+        recorder.snap(&mut window).unwrap();
     }
+
+    recorder.finalize().unwrap();
 }
@@ -0,0 +1,643 @@
+//! Configuration of a video recorder.
+
+use std::ptr;
+use std::ffi::{CString, OsStr};
+use std::path::PathBuf;
+use avutil;
+use swscale;
+#[cfg(feature = "kiss3d")]
+use kiss3d::window::Window;
+use overlay::TimestampOverlay;
+use Recorder;
+
+/// An encoder preset, trading encoding speed for compression efficiency.
+///
+/// At the same bit rate or quality setting, a slower preset produces a smaller file, or a better
+/// looking one, but takes more CPU time per frame. For realtime capture, pick the slowest preset
+/// that keeps up with the frame rate; for offline rendering, slower presets are usually worth it.
+/// Presets are supported by libx264 and libx265; other encoders ignore them, and report the
+/// option in `Recorder::unused_codec_options`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// The fastest preset, with the biggest files. Suitable for realtime capture of large windows.
+    UltraFast,
+    /// A fast preset, still affordable for realtime capture on a fast machine.
+    Fast,
+    /// The default preset of the encoders.
+    Medium,
+    /// A slow preset, for offline rendering.
+    Slow,
+    /// The slowest preset worth using, with the smallest files.
+    VerySlow
+}
+
+impl Preset {
+    /// The name of the preset, as given to the "preset" codec option.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Preset::UltraFast => "ultrafast",
+            Preset::Fast      => "fast",
+            Preset::Medium    => "medium",
+            Preset::Slow      => "slow",
+            Preset::VerySlow  => "veryslow"
+        }
+    }
+}
+
+/// A profile of the ProRes encoder, trading file size for quality.
+///
+/// The bit rate is set by the profile, about 45, 100, 145 and 220Mbps respectively for 1080p at
+/// 30fps: the bit rate and quality settings of the builder have no effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProResProfile {
+    /// The smallest files, for offline editing.
+    Proxy,
+    /// A lighter variant of the standard profile.
+    Lt,
+    /// The standard profile.
+    Standard,
+    /// The high quality profile, visually lossless.
+    Hq
+}
+
+impl ProResProfile {
+    /// The name of the profile, as given to the "profile" codec option of "prores_ks".
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ProResProfile::Proxy    => "proxy",
+            ProResProfile::Lt       => "lt",
+            ProResProfile::Standard => "standard",
+            ProResProfile::Hq       => "hq"
+        }
+    }
+}
+
+/// Builder of a video recorder with custom recording parameters.
+///
+/// Every parameter left unset keeps its default value.
+#[derive(Clone, Debug)]
+pub struct RecorderBuilder {
+    bit_rate:     usize,
+    crf:          Option<f64>,
+    qscale:       Option<f64>,
+    time_base:    (usize, usize),
+    stream_time_base: (usize, usize),
+    gop_size:     usize,
+    keyframe_interval: Option<f64>,
+    max_b_frames: usize,
+    pix_fmt:      Option<i32>,
+    scale_flags:  i32,
+    color_range:  Option<i32>,
+    colorspace:   Option<i32>,
+    color_primaries: Option<i32>,
+    pace_realtime: bool,
+    quality_stats: bool,
+    capture_every: usize,
+    encoder_threads: Option<usize>,
+    segment_duration: Option<f64>,
+    sync_every:   Option<usize>,
+    burn_timestamp: bool,
+    timestamp_position: (usize, usize),
+    timestamp_color: (u8, u8, u8),
+    stereo:       bool,
+    format_name:  Option<String>,
+    fallback_format: Option<String>,
+    codec_name:   Option<String>,
+    codec_options: Vec<(CString, CString)>,
+    metadata:     Vec<(CString, CString)>
+}
+
+impl RecorderBuilder {
+    /// Creates a new builder with default recording parameters.
+    pub fn new() -> RecorderBuilder {
+        RecorderBuilder {
+            bit_rate:     400000, // FIXME
+            crf:          None,
+            qscale:       None,
+            time_base:    (1, 60),
+            stream_time_base: (1, 90000),
+            gop_size:     10,
+            keyframe_interval: None,
+            max_b_frames: 1,
+            pix_fmt:      None,
+            scale_flags:  swscale::SWS_BICUBIC as i32,
+            color_range:  None,
+            colorspace:   None,
+            color_primaries: None,
+            pace_realtime: false,
+            quality_stats: false,
+            capture_every: 1,
+            encoder_threads: None,
+            segment_duration: None,
+            sync_every:   None,
+            burn_timestamp: false,
+            timestamp_position: (8, 8),
+            timestamp_color: (255, 255, 255),
+            stereo:       false,
+            format_name:  None,
+            fallback_format: Some("mpeg".to_string()),
+            codec_name:   None,
+            codec_options: Vec::new(),
+            metadata:     Vec::new()
+        }
+    }
+
+    /// Sets the average bit rate. Default value: 400000.
+    ///
+    /// This switches the encoder to bit rate mode, cancelling any previous call to `crf` or
+    /// `qscale`.
+    pub fn bit_rate(mut self, bit_rate: usize) -> RecorderBuilder {
+        self.bit_rate = bit_rate;
+        self.crf      = None;
+        self.qscale   = None;
+        self
+    }
+
+    /// Switches the encoder to constant quality mode with the given Constant Rate Factor.
+    ///
+    /// The encoder then ignores the bit rate and targets a constant visual quality instead; lower
+    /// values mean better quality and bigger files. For x264, 0 is near-lossless and the usual
+    /// range is 18 to 28. Only encoders exposing a "crf" option support this mode; the others
+    /// fail to initialize with `RecorderError::CodecOption`. This cancels any previous call to
+    /// `bit_rate` or `qscale`.
+    pub fn crf(mut self, crf: f64) -> RecorderBuilder {
+        self.crf    = Some(crf);
+        self.qscale = None;
+        self
+    }
+
+    /// Switches the encoder to constant quantizer mode with the given quantizer scale. See
+    /// `Recorder::set_qscale`. This cancels any previous call to `bit_rate` or `crf`.
+    pub fn qscale(mut self, q: f64) -> RecorderBuilder {
+        assert!(q > 0.0, "The quantizer scale must be positive.");
+
+        self.qscale = Some(q);
+        self.crf    = None;
+        self
+    }
+
+    /// Sets the fundamental unit of time (in seconds) in terms of which frame timestamps are
+    /// represented. Default value: (1, 60), i-e, 60fps.
+    pub fn time_base(mut self, time_base: (usize, usize)) -> RecorderBuilder {
+        self.time_base = time_base;
+        self
+    }
+
+    /// Sets the unit of time of the timestamps stored in the container. Default value:
+    /// (1, 90000), the MPEG clock.
+    ///
+    /// This is independent of `time_base` and `fps`, which set the frame rate of the encoder: the
+    /// frames are timestamped in whole frames of that rate, including those of `snap_at`, and
+    /// their packets converted to this unit when written. It must be at least as fine as the
+    /// frame duration for the frame rate to be exact. Some containers impose their own unit,
+    /// e.g. hundredths of a second for GIF, and override this setting.
+    pub fn stream_time_base(mut self, time_base: (usize, usize)) -> RecorderBuilder {
+        assert!(time_base.0 > 0 && time_base.1 > 0, "The stream time base must be positive.");
+
+        self.stream_time_base = time_base;
+        self
+    }
+
+    /// Sets the frame rate, in frames per second.
+    ///
+    /// This is a more intuitive alternative to `time_base`. The rate is converted to an exact
+    /// rational time base whenever possible: the NTSC rates 23.976, 29.97 and 59.94 become
+    /// (1001, 24000), (1001, 30000) and (1001, 60000). Other rates are approximated with a
+    /// numerator and denominator clamped to 65535, the limit of many codecs.
+    pub fn fps(mut self, fps: f64) -> RecorderBuilder {
+        assert!(fps > 0.0, "The frame rate must be positive.");
+
+        // NTSC-like rates are n * 1000 / 1001 frames per second. Whole rates never are, and the
+        // denominator must stay within the clamp of other rates.
+        let ntsc    = fps * 1.001;
+        let is_ntsc = fps != fps.round() && ntsc.round() >= 1.0 && ntsc.round() <= 65.0 &&
+                      (ntsc - ntsc.round()).abs() < 1.0e-3;
+
+        self.time_base = if is_ntsc {
+            (1001, ntsc.round() as usize * 1000)
+        }
+        else {
+            let q = unsafe { avutil::av_d2q(1.0 / fps, 65535) };
+
+            (q.num as usize, q.den as usize)
+        };
+
+        self
+    }
+
+    /// Sets the number of pictures in a group of pictures. Default value: 10.
+    ///
+    /// Intra-only encoders use a GOP size of 0 regardless, and the GOP size is raised if it cannot
+    /// hold `max_b_frames` B-frames. Such adjustments are logged as FFmpeg warnings.
+    ///
+    /// This cancels any previous call to `keyframe_interval_secs`.
+    pub fn gop_size(mut self, gop_size: usize) -> RecorderBuilder {
+        self.gop_size          = gop_size;
+        self.keyframe_interval = None;
+        self
+    }
+
+    /// Sets the interval between keyframes in seconds rather than frames, e.g. 2 seconds for HLS
+    /// streaming. Default: a GOP size of 10 frames.
+    ///
+    /// The GOP size is computed at initialization from the frame rate, including the effect of
+    /// `capture_every_nth`: it is the interval divided by the frame duration, rounded to the
+    /// nearest whole number of frames, and at least 1. It is then adjusted like a `gop_size`.
+    /// This cancels any previous call to `gop_size`.
+    pub fn keyframe_interval_secs(mut self, secs: f64) -> RecorderBuilder {
+        assert!(secs > 0.0, "The keyframe interval must be positive.");
+
+        self.keyframe_interval = Some(secs);
+        self
+    }
+
+    /// Sets the maximum number of B-frames between non-B-frames. Default value: 1.
+    ///
+    /// This is clamped to what the encoder supports, e.g. 0 for encoders without B-frames. Such
+    /// adjustments are logged as FFmpeg warnings.
+    pub fn max_b_frames(mut self, max_b_frames: usize) -> RecorderBuilder {
+        self.max_b_frames = max_b_frames;
+        self
+    }
+
+    /// Sets the pixel format. Default value: `avutil::PIX_FMT_YUV420P` if the encoder supports
+    /// it (`avutil::PIX_FMT_YUVJ420P` for MJPEG), otherwise the first format it supports.
+    ///
+    /// The captured images are converted to this format. `avutil::PIX_FMT_YUV420P` halves the
+    /// chroma resolution in both directions, which blurs sharp colored edges such as text or
+    /// vector graphics. `avutil::PIX_FMT_YUV422P` keeps the full vertical chroma resolution, and
+    /// `avutil::PIX_FMT_YUV444P` the full resolution, at the cost of bigger files and of a
+    /// narrower player support (e.g. libx264 then produces the High 4:2:2 or 4:4:4 profile).
+    /// Formats the encoder does not support make the initialization fail with
+    /// `RecorderError::UnsupportedPixelFormat`.
+    pub fn pix_fmt(mut self, pix_fmt: i32) -> RecorderBuilder {
+        self.pix_fmt = Some(pix_fmt);
+        self
+    }
+
+    /// Keeps the alpha channel of the images given to `Recorder::snap_rgba`, for later
+    /// compositing.
+    ///
+    /// This selects the `avutil::PIX_FMT_YUVA420P` pixel format, which requires an encoder and
+    /// container supporting transparency, e.g. "libvpx-vp9" in WebM. Other encoders fail to
+    /// initialize with `RecorderError::UnsupportedPixelFormat`. Window snapshots have no alpha
+    /// channel and stay opaque.
+    pub fn preserve_alpha(self) -> RecorderBuilder {
+        self.pix_fmt(avutil::PIX_FMT_YUVA420P)
+    }
+
+    /// Sets the color range of YUV videos: `avcodec::AVCOL_RANGE_MPEG` (limited, 16-235) or
+    /// `avcodec::AVCOL_RANGE_JPEG` (full, 0-255).
+    ///
+    /// The range is both used for the conversion and written into the stream, so that players
+    /// interpret the colors correctly. Default value: full range for the JPEG pixel formats,
+    /// limited range otherwise.
+    pub fn color_range(mut self, color_range: i32) -> RecorderBuilder {
+        self.color_range = Some(color_range);
+        self
+    }
+
+    /// Sets the YUV matrix of YUV videos, e.g. `avcodec::AVCOL_SPC_BT709` or
+    /// `avcodec::AVCOL_SPC_SMPTE170M` (BT.601).
+    ///
+    /// As for `color_range`, the matrix is used for the conversion and written into the stream.
+    /// Default value: BT.709 for videos at least 720 pixels high, BT.601 otherwise.
+    pub fn colorspace(mut self, colorspace: i32) -> RecorderBuilder {
+        self.colorspace = Some(colorspace);
+        self
+    }
+
+    /// Sets the color primaries written into the stream of YUV videos, e.g.
+    /// `avcodec::AVCOL_PRI_BT709`. Default value: the primaries matching the default
+    /// `colorspace`.
+    pub fn color_primaries(mut self, color_primaries: i32) -> RecorderBuilder {
+        self.color_primaries = Some(color_primaries);
+        self
+    }
+
+    /// Sets the algorithm used to rescale and convert the captured images, e.g.
+    /// `swscale::SWS_FAST_BILINEAR` for fast realtime capture or `swscale::SWS_LANCZOS` for
+    /// high-quality downscaling. Default value: `swscale::SWS_BICUBIC`.
+    ///
+    /// The conversion always runs as a single slice on the thread calling `snap`: the scaler of
+    /// the FFmpeg versions supported here has no multithreading, and slicing the image by hand
+    /// would break the vertical filtering at the slice boundaries. For large captures, prefer a
+    /// cheaper algorithm, or a `ThreadedRecorder` to move the conversion off the render loop.
+    pub fn scale_flags(mut self, scale_flags: i32) -> RecorderBuilder {
+        self.scale_flags = scale_flags;
+        self
+    }
+
+    /// Enables real-time pacing, so that the playback duration matches the wall-clock duration of
+    /// the recording. See `Recorder::set_pace_realtime`. Default value: false.
+    pub fn pace_realtime(mut self, enabled: bool) -> RecorderBuilder {
+        self.pace_realtime = enabled;
+        self
+    }
+
+    /// Pushes the encoded video to the output every `n_frames` written packets, so that a crash
+    /// loses at most the last `n_frames` written packets, plus the frames still buffered inside
+    /// the encoder. Default: the output is only complete once finalized.
+    ///
+    /// Encoders hold frames back for their lookahead, B-frames and frame threads: libx264 keeps
+    /// up to about 40 frames by default. Those are not written before the encoder outputs them,
+    /// so they are lost as well; a low-latency setting, e.g. the "tune" codec option set to
+    /// "zerolatency" for libx264, reduces that delay.
+    ///
+    /// This is a crash-resilience feature, not a way to append to an existing recording, which
+    /// most containers do not support. Each sync flushes the muxer and the I/O buffers, and ends
+    /// the current fragment of fragmented formats. MP4 and MOV outputs are written as fragmented
+    /// MP4 for this purpose, since their regular index is only written by `finalize`. Streamable
+    /// formats like Matroska or MPEG-TS are readable up to the last sync as they are.
+    /// Frequent syncs cost some I/O and, for MP4, some file size.
+    pub fn sync_every(mut self, n_frames: usize) -> RecorderBuilder {
+        assert!(n_frames > 0, "The sync interval must be positive.");
+
+        self.sync_every = Some(n_frames);
+        self
+    }
+
+    /// Burns the frame index and the elapsed time into every recorded frame, e.g. "#120 2.000s".
+    /// Default value: false.
+    ///
+    /// The text is drawn with a tiny built-in font, in `timestamp_color` on a black background,
+    /// at `timestamp_position`. It is drawn on the captured image, before scaling, so it is
+    /// shrunk along with the image when recording a large window into a small video. This helps
+    /// matching the frames of the recording with the events of the application, or the frames of
+    /// several synchronized recordings.
+    pub fn burn_timestamp(mut self, enabled: bool) -> RecorderBuilder {
+        self.burn_timestamp = enabled;
+        self
+    }
+
+    /// Sets the position of the top-left corner of the burnt-in timestamp, in pixels from the
+    /// top-left corner of the captured image. Default value: (8, 8). See `burn_timestamp`.
+    pub fn timestamp_position(mut self, x: usize, y: usize) -> RecorderBuilder {
+        self.timestamp_position = (x, y);
+        self
+    }
+
+    /// Sets the color of the burnt-in timestamp. Default value: (255, 255, 255), i-e, white. See
+    /// `burn_timestamp`.
+    pub fn timestamp_color(mut self, r: u8, g: u8, b: u8) -> RecorderBuilder {
+        self.timestamp_color = (r, g, b);
+        self
+    }
+
+    /// Tags the video as side-by-side stereoscopic 3D, the left view in the left half of each
+    /// image. Default value: false.
+    ///
+    /// The stereo layout is signaled in the bitstream by encoders that support it, like libx264,
+    /// and in the container by Matroska and WebM, so that compatible players and VR headsets show
+    /// the video in 3D. Feed the views with `Recorder::snap_stereo`, or composite them yourself
+    /// into images twice as wide.
+    pub fn stereo_side_by_side(mut self, enabled: bool) -> RecorderBuilder {
+        self.stereo = enabled;
+        self
+    }
+
+    /// Makes the encoder measure the quality of each frame, reported as `ProgressInfo::quality`.
+    /// Default value: false.
+    ///
+    /// The quality is the PSNR of the luma plane, comparing each encoded frame with its source.
+    /// Computing it slows down the encoding a bit, so leave this disabled outside of tuning
+    /// sessions, e.g. comparing file sizes and quality across `crf` values. Encoders that do not
+    /// compute the PSNR, like libvpx, report infinity.
+    pub fn enable_quality_stats(mut self, enabled: bool) -> RecorderBuilder {
+        self.quality_stats = enabled;
+        self
+    }
+
+    /// Splits the recording into consecutive files of `secs` seconds each. See
+    /// `Recorder::set_segment_duration`. Default: a single file.
+    pub fn segment_duration_secs(mut self, secs: f64) -> RecorderBuilder {
+        assert!(secs > 0.0, "The segment duration must be positive.");
+
+        self.segment_duration = Some(secs);
+        self
+    }
+
+    /// Sets the number of threads the encoder may use, 0 meaning one per CPU core. See
+    /// `Recorder::set_encoder_threads`. Default: the encoder default, usually a single thread.
+    pub fn encoder_threads(mut self, threads: usize) -> RecorderBuilder {
+        self.encoder_threads = Some(threads);
+        self
+    }
+
+    /// Selects the container format by name instead of guessing it from the output path. See
+    /// `Recorder::force_format`. Default: guessed from the path.
+    pub fn force_format(mut self, name: &str) -> RecorderBuilder {
+        self.format_name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the container format used when it cannot be guessed from the output path, e.g. for a
+    /// path without extension. Default value: `Some("mpeg")`, i-e, MPEG-1 video in an MPEG-PS
+    /// file, whatever the extension.
+    ///
+    /// With `None`, the initialization fails with `RecorderError::UnknownFormat` instead, which
+    /// catches mistyped extensions early. This has no effect when the format is set with
+    /// `force_format`, or for live stream URLs.
+    pub fn fallback_format(mut self, name: Option<&str>) -> RecorderBuilder {
+        self.fallback_format = name.map(|name| name.to_string());
+        self
+    }
+
+    /// Encodes only one snapshot out of `n`. See `Recorder::set_capture_every_nth`. Default
+    /// value: 1.
+    pub fn capture_every_nth(mut self, n: usize) -> RecorderBuilder {
+        assert!(n > 0, "At least one snapshot out of n must be captured.");
+
+        self.capture_every = n;
+        self
+    }
+
+    /// Selects the all-intra MJPEG encoder, for frame-accurate editing and analysis.
+    ///
+    /// Every frame is a keyframe, decodable on its own, at the cost of much bigger files. The
+    /// frames are encoded in full-range `avutil::PIX_FMT_YUVJ420P`. Use a container supporting
+    /// MJPEG, e.g. ".avi", ".mov" or ".mkv".
+    pub fn mjpeg(self) -> RecorderBuilder {
+        self.codec_name("mjpeg").gop_size(0).max_b_frames(0)
+    }
+
+    /// Selects the ProRes encoder, the intermediate format preferred by editors like DaVinci
+    /// Resolve or Final Cut Pro.
+    ///
+    /// Every frame is a keyframe, encoded in 10-bit 4:2:2 `avutil::PIX_FMT_YUV422P10LE`. This
+    /// requires FFmpeg built with the "prores_ks" encoder, and a ".mov" output.
+    pub fn prores(self, profile: ProResProfile) -> RecorderBuilder {
+        self.codec_name("prores_ks")
+            .pix_fmt(avutil::PIX_FMT_YUV422P10LE)
+            .codec_option("profile", profile.name())
+            .gop_size(0)
+            .max_b_frames(0)
+    }
+
+    /// Selects the encoder by name, e.g. "libx264" or "libvpx-vp9". Default: the default encoder
+    /// of the output container.
+    pub fn codec_name(mut self, name: &str) -> RecorderBuilder {
+        self.codec_name = Some(name.to_string());
+        self
+    }
+
+    /// Sets a private option of the encoder, e.g. "preset" to "ultrafast" for libx264.
+    ///
+    /// Panics if `key` or `value` contain a nul byte.
+    pub fn codec_option(mut self, key: &str, value: &str) -> RecorderBuilder {
+        self.codec_options.push((CString::new(key).unwrap(), CString::new(value).unwrap()));
+        self
+    }
+
+    /// Selects an encoder preset. This is the same as setting the "preset" codec option.
+    pub fn preset(self, preset: Preset) -> RecorderBuilder {
+        self.codec_option("preset", preset.name())
+    }
+
+    /// Sets a metadata tag of the output container, e.g. "title", "artist" or "comment".
+    ///
+    /// Panics if `key` or `value` contain a nul byte.
+    pub fn metadata(mut self, key: &str, value: &str) -> RecorderBuilder {
+        self.metadata.push((CString::new(key).unwrap(), CString::new(value).unwrap()));
+        self
+    }
+
+    /// Creates a video recorder with the size of the window framebuffer.
+    ///
+    /// See `Recorder::for_window` for the handling of HiDPI displays.
+    #[cfg(feature = "kiss3d")]
+    pub fn build_for_window<P: ?Sized + AsRef<OsStr>>(&self, window: &Window, path: &P) -> Recorder {
+        let mut buf = Vec::new();
+
+        // If the window has not rendered yet, its size is taken from the first snapshot instead.
+        match ::snap_window(window, &mut buf) {
+            Ok((width, height)) => self.build(path, width, height),
+            Err(_)              => self.build(path, 0, 0)
+        }
+    }
+
+    /// Creates the video recorder.
+    ///
+    /// Captured images are rescaled to the size of the recorded video.
+    ///
+    /// # Arguments:
+    /// * `path`   - path to the output file.
+    /// * `width`  - width of the recorded video. Rounded up to a multiple of two. If zero, the
+    ///              size of the first captured image is used.
+    /// * `height` - height of the recorded video. Rounded up to a multiple of two. If zero, the
+    ///              size of the first captured image is used.
+    pub fn build<P: ?Sized + AsRef<OsStr>>(&self, path: &P, width: usize, height: usize) -> Recorder {
+        ::register_all();
+
+        let mut recorder = Recorder {
+            initialized:      false,
+            finalized:        false,
+            paused:           false,
+            paused_at:        None,
+            pace_realtime:    self.pace_realtime,
+            quality_stats:    self.quality_stats,
+            force_keyframe:   false,
+            irregular_pts:    false,
+            capture_every:    self.capture_every,
+            capture_calls:    0,
+            frame_limit:      None,
+            finalize_at_limit: false,
+            realtime_start:   None,
+            bytes_written:    0,
+            packets_written:  0,
+            write_error:      None,
+            sync_every:       self.sync_every,
+            progress_callback: None,
+            curr_frame_index: 0,
+            bit_rate:         self.bit_rate,
+            crf:              self.crf,
+            qscale:           self.qscale,
+            width:            ::round_up_to_even(width),
+            height:           ::round_up_to_even(height),
+            time_base:        self.time_base,
+            stream_time_base: self.stream_time_base,
+            gop_size:         self.gop_size,
+            keyframe_interval: self.keyframe_interval,
+            max_b_frames:     self.max_b_frames,
+            pix_fmt:          self.pix_fmt,
+            scale_flags:      self.scale_flags,
+            color_range:      self.color_range,
+            colorspace:       self.colorspace,
+            color_primaries:  self.color_primaries,
+            encoder_threads:  self.encoder_threads,
+            pass_flags:       0,
+            stats_in:         None,
+            stats_out:        String::new(),
+            codec_name:       self.codec_name.clone(),
+            codec_options:    self.codec_options.clone(),
+            unused_options:   Vec::new(),
+            metadata:         self.metadata.clone(),
+            capture_region:   None,
+            timestamp_overlay: if self.burn_timestamp {
+                Some(TimestampOverlay {
+                    x:     self.timestamp_position.0,
+                    y:     self.timestamp_position.1,
+                    color: self.timestamp_color
+                })
+            }
+            else {
+                None
+            },
+            stereo:           self.stereo,
+            input_format:     None,
+            frame:            ptr::null_mut(),
+            tmp_frame:        ptr::null_mut(),
+            packet:           ::new_packet(),
+            context:          ptr::null_mut(),
+            scale_context:    ptr::null_mut(),
+            format_context:   ptr::null_mut(),
+            video_st:         ptr::null_mut(),
+            path:             PathBuf::from(path),
+            segment_duration: None,
+            segment_index:    0,
+            segment_base:     PathBuf::new(),
+            segment_frames:   0,
+            format_name:      self.format_name.clone(),
+            fallback_format:  self.fallback_format.clone(),
+            custom_output:    None,
+            frame_buf:        Vec::new(),
+            tmp_frame_buf:    Vec::new()
+        };
+
+        if let Some(secs) = self.segment_duration {
+            let _ = recorder.set_segment_duration(secs);
+        }
+
+        recorder
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RecorderBuilder;
+
+    #[test]
+    fn fps_gives_whole_rates_a_unit_numerator() {
+        assert_eq!(RecorderBuilder::new().fps(60.0).time_base, (1, 60));
+        assert_eq!(RecorderBuilder::new().fps(30.0).time_base, (1, 30));
+        assert_eq!(RecorderBuilder::new().fps(25.0).time_base, (1, 25));
+        assert_eq!(RecorderBuilder::new().fps(1.0).time_base, (1, 1));
+        assert_eq!(RecorderBuilder::new().fps(1000.0).time_base, (1, 1000));
+    }
+
+    #[test]
+    fn fps_keeps_ntsc_rates_exact() {
+        assert_eq!(RecorderBuilder::new().fps(23.976).time_base, (1001, 24000));
+        assert_eq!(RecorderBuilder::new().fps(29.97).time_base,  (1001, 30000));
+        assert_eq!(RecorderBuilder::new().fps(59.94).time_base,  (1001, 60000));
+    }
+
+    #[test]
+    fn fps_clamps_the_time_base() {
+        let (num, den) = RecorderBuilder::new().fps(12345.678).time_base;
+
+        assert!(num > 0 && num <= 65535);
+        assert!(den > 0 && den <= 65535);
+        assert!((den as f64 / num as f64 - 12345.678).abs() < 0.1);
+    }
+}
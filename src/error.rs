@@ -0,0 +1,314 @@
+//! Errors reported by the video recorder.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ffi::{CStr, NulError};
+use std::path::PathBuf;
+use libc::c_char;
+use avutil;
+
+/// An error that occurred while recording a video.
+#[derive(Debug)]
+pub enum RecorderError {
+    /// The output path cannot be converted to a C string.
+    InvalidPath(PathBuf),
+    /// A setting was changed after the recorder was initialized, where it has no effect.
+    AlreadyInitialized,
+    /// The recording was already finished, and its encoder and output closed.
+    Closed,
+    /// The video size is zero and could not be derived from a captured image. Holds the width and
+    /// the height.
+    InvalidDimensions(usize, usize),
+    /// An argument is out of its valid range. Holds a description of the constraint.
+    InvalidArgument(String),
+    /// The output format context could not be created.
+    OutputContextCreation,
+    /// The container format cannot be guessed from the output path, and no fallback format is
+    /// set. Holds the path.
+    UnknownFormat(PathBuf),
+    /// The selected output container does not support video.
+    NoVideoSupport,
+    /// No encoder was found for the given codec id.
+    CodecNotFound(i32),
+    /// No encoder with the given name is available in the linked FFmpeg.
+    EncoderNotFound(String),
+    /// The output container cannot hold the selected codec. Holds the names of the encoder and
+    /// of the container.
+    IncompatibleCodecContainer(String, String),
+    /// The video stream could not be allocated.
+    StreamAllocation,
+    /// The video codec context could not be allocated.
+    ContextAllocation,
+    /// A codec option was rejected by the encoder. Holds the option name and the FFmpeg return
+    /// code.
+    CodecOption(String, i32),
+    /// A string given to FFmpeg, e.g. a codec option or a metadata tag, contains a nul byte.
+    NulByte(NulError),
+    /// The encoder does not support the requested pixel format. Holds the requested format and
+    /// the formats the encoder supports.
+    UnsupportedPixelFormat(i32, Vec<i32>),
+    /// The codec could not be opened. Holds the FFmpeg return code.
+    CodecOpen(i32),
+    /// A hardware encoder could not be opened, usually because no suitable GPU or driver is
+    /// available. Holds the encoder name and the FFmpeg return code.
+    HardwareEncoderUnavailable(String, i32),
+    /// A video frame could not be allocated.
+    FrameAllocation,
+    /// The capture region does not intersect the window.
+    EmptyCaptureRegion,
+    /// A recorder of a `MultiRecorder` has a capture region, which cannot apply to the window
+    /// image shared by every recorder.
+    CaptureRegionUnsupported,
+    /// An image buffer does not match the size of the image, e.g. when the window returns a
+    /// partial image before its first frame is rendered. Holds the expected and actual lengths of
+    /// the buffer.
+    IncompleteFrame(usize, usize),
+    /// A timestamp given to `snap_at` is not after the previous one. Holds the timestamp.
+    NonMonotonicTimestamp(f64),
+    /// An image of a sequence could not be read. Holds its path and the reason.
+    ImageDecoding(PathBuf, String),
+    /// An image of a sequence differs in size from the first one. Holds its path, width and
+    /// height.
+    InconsistentFrameSize(PathBuf, usize, usize),
+    /// An image sequence directory contains no image. Holds the directory.
+    EmptyImageSequence(PathBuf),
+    /// The bit rate cannot be changed because the encoder runs in constant quality or constant
+    /// quantizer mode.
+    ConstantQualityMode,
+    /// The output file could not be opened. Holds the path and the FFmpeg return code.
+    FileOpen(PathBuf, i32),
+    /// The in-memory output could not be allocated.
+    AvioAllocation,
+    /// The operation needs an output file, but the recorder writes into memory or a writer.
+    NoOutputFile,
+    /// The operation needs an in-memory output, but the recorder writes into a file or a writer.
+    NotInMemory,
+    /// The container header could not be written. Holds the FFmpeg return code.
+    HeaderWriting(i32),
+    /// The encoder failed to encode a frame.
+    ///
+    /// Holds the raw FFmpeg return code and its description.
+    FrameEncoding(i32, String),
+    /// An encoded packet could not be written to the output file.
+    ///
+    /// Holds the raw FFmpeg return code and its description.
+    FrameWriting(i32, String),
+    /// The container trailer could not be written. Holds the FFmpeg return code.
+    TrailerWriting(i32),
+    /// The output file could not be closed. Holds the FFmpeg return code.
+    FileClose(i32),
+    /// The background encoding thread stopped unexpectedly.
+    WorkerStopped,
+    /// Some outputs of a `MultiRecorder` failed. Holds the index of each failed recorder, with
+    /// its error.
+    MultipleOutputs(Vec<(usize, RecorderError)>),
+    /// An I/O error occurred while writing an output file.
+    Io(io::Error)
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecorderError::InvalidPath(ref path) =>
+                write!(f, "Invalid output path: {}.", path.display()),
+            RecorderError::AlreadyInitialized =>
+                write!(f, "This setting cannot be changed once the recorder is initialized."),
+            RecorderError::Closed =>
+                write!(f, "The recording is already finished."),
+            RecorderError::InvalidDimensions(width, height) =>
+                write!(f, "Invalid video size {}x{}: the size must be nonzero, or derived from a first snapshot.",
+                       width, height),
+            RecorderError::InvalidArgument(ref msg) =>
+                write!(f, "Invalid argument: {}.", msg),
+            RecorderError::OutputContextCreation =>
+                write!(f, "Unable to create the output context."),
+            RecorderError::UnknownFormat(ref path) =>
+                write!(f, "Could not guess the container format of {}. Check its extension.", path.display()),
+            RecorderError::NoVideoSupport =>
+                write!(f, "The selected output container does not support video encoding."),
+            RecorderError::CodecNotFound(id) =>
+                write!(f, "Codec not found (codec id {}).", id),
+            RecorderError::EncoderNotFound(ref name) =>
+                write!(f, "Encoder not found: {}.", name),
+            RecorderError::IncompatibleCodecContainer(ref codec, ref container) =>
+                write!(f, "The {} container cannot hold {} video. Try a {} file instead.",
+                       container, codec, suggested_extension(codec)),
+            RecorderError::StreamAllocation =>
+                write!(f, "Failed to allocate the video stream."),
+            RecorderError::ContextAllocation =>
+                write!(f, "Could not allocate video codec context."),
+            RecorderError::CodecOption(ref name, code) =>
+                write!(f, "Invalid codec option {}: {} (code {}).", name, ffmpeg_error_string(code), code),
+            RecorderError::NulByte(ref err) =>
+                write!(f, "A string contains a nul byte at position {}.", err.nul_position()),
+            RecorderError::UnsupportedPixelFormat(pix_fmt, ref supported) => {
+                let names: Vec<String> = supported.iter().map(|f| pix_fmt_name(*f)).collect();
+
+                write!(f, "The encoder does not support the pixel format {}. Supported formats: {}.",
+                       pix_fmt_name(pix_fmt), names.connect(", "))
+            },
+            RecorderError::CodecOpen(code) =>
+                write!(f, "Could not open the codec: {} (code {}).", ffmpeg_error_string(code), code),
+            RecorderError::HardwareEncoderUnavailable(ref name, code) =>
+                write!(f, "The hardware encoder {} is unavailable: {} (code {}). Is a suitable GPU \
+                           and driver installed?", name, ffmpeg_error_string(code), code),
+            RecorderError::FrameAllocation =>
+                write!(f, "Could not allocate the video frame."),
+            RecorderError::EmptyCaptureRegion =>
+                write!(f, "The capture region does not intersect the window."),
+            RecorderError::CaptureRegionUnsupported =>
+                write!(f, "Capture regions are not supported by recorders fed by a MultiRecorder."),
+            RecorderError::IncompleteFrame(expected, actual) =>
+                write!(f, "The image buffer is of length {} instead of {}.", actual, expected),
+            RecorderError::NonMonotonicTimestamp(t) =>
+                write!(f, "The timestamp {}s is not after the previous frame.", t),
+            RecorderError::ImageDecoding(ref path, ref msg) =>
+                write!(f, "Failed to read the image {}: {}.", path.display(), msg),
+            RecorderError::InconsistentFrameSize(ref path, width, height) =>
+                write!(f, "The image {} is {}x{}, unlike the previous images of the sequence.",
+                       path.display(), width, height),
+            RecorderError::EmptyImageSequence(ref dir) =>
+                write!(f, "No image found in {}.", dir.display()),
+            RecorderError::ConstantQualityMode =>
+                write!(f, "The encoder runs in constant quality mode and has no bit rate to change."),
+            RecorderError::FileOpen(ref path, code) =>
+                write!(f, "Failed to open the output file {}: {} (code {}).",
+                       path.display(), ffmpeg_error_string(code), code),
+            RecorderError::AvioAllocation =>
+                write!(f, "Failed to allocate the in-memory output."),
+            RecorderError::NoOutputFile =>
+                write!(f, "The recorder writes into memory or a writer, not into a file."),
+            RecorderError::NotInMemory =>
+                write!(f, "The recorder does not write into memory."),
+            RecorderError::HeaderWriting(code) =>
+                write!(f, "Failed to write the output header: {} (code {}).", ffmpeg_error_string(code), code),
+            RecorderError::FrameEncoding(code, ref msg) =>
+                write!(f, "Error encoding frame: {} (code {}).", msg, code),
+            RecorderError::FrameWriting(code, ref msg) =>
+                write!(f, "Error writing frame: {} (code {}).", msg, code),
+            RecorderError::TrailerWriting(code) =>
+                write!(f, "Failed to write the output trailer: {} (code {}).", ffmpeg_error_string(code), code),
+            RecorderError::FileClose(code) =>
+                write!(f, "Failed to close the output file: {} (code {}).", ffmpeg_error_string(code), code),
+            RecorderError::WorkerStopped =>
+                write!(f, "The background encoding thread stopped unexpectedly."),
+            RecorderError::MultipleOutputs(ref errors) => {
+                let msgs: Vec<String> = errors.iter().map(|&(i, ref e)| format!("output {}: {}", i, e)).collect();
+
+                write!(f, "Some outputs failed: {}", msgs.connect(" "))
+            },
+            RecorderError::Io(ref err) =>
+                write!(f, "I/O error: {}.", err)
+        }
+    }
+}
+
+impl Error for RecorderError {
+    fn description(&self) -> &str {
+        match *self {
+            RecorderError::InvalidPath(..)                => "invalid output path",
+            RecorderError::AlreadyInitialized             => "recorder already initialized",
+            RecorderError::Closed                         => "recording already finished",
+            RecorderError::InvalidDimensions(..)          => "invalid video size",
+            RecorderError::InvalidArgument(..)            => "invalid argument",
+            RecorderError::OutputContextCreation          => "unable to create the output context",
+            RecorderError::UnknownFormat(..)              => "unknown container format",
+            RecorderError::NoVideoSupport                 => "output container does not support video",
+            RecorderError::CodecNotFound(..)              => "codec not found",
+            RecorderError::EncoderNotFound(..)            => "encoder not found",
+            RecorderError::IncompatibleCodecContainer(..) => "codec not supported by the container",
+            RecorderError::StreamAllocation               => "failed to allocate the video stream",
+            RecorderError::ContextAllocation              => "could not allocate video codec context",
+            RecorderError::CodecOption(..)                => "invalid codec option",
+            RecorderError::NulByte(..)                    => "nul byte in a string",
+            RecorderError::UnsupportedPixelFormat(..)     => "unsupported pixel format",
+            RecorderError::CodecOpen(..)                  => "could not open the codec",
+            RecorderError::HardwareEncoderUnavailable(..) => "hardware encoder unavailable",
+            RecorderError::FrameAllocation                => "could not allocate the video frame",
+            RecorderError::EmptyCaptureRegion             => "empty capture region",
+            RecorderError::CaptureRegionUnsupported       => "capture region not supported",
+            RecorderError::IncompleteFrame(..)            => "incomplete frame",
+            RecorderError::NonMonotonicTimestamp(..)      => "non-monotonic timestamp",
+            RecorderError::ImageDecoding(..)              => "failed to read an image",
+            RecorderError::InconsistentFrameSize(..)      => "inconsistent image size",
+            RecorderError::EmptyImageSequence(..)         => "empty image sequence",
+            RecorderError::ConstantQualityMode            => "encoder in constant quality mode",
+            RecorderError::FileOpen(..)                   => "failed to open the output file",
+            RecorderError::AvioAllocation                 => "failed to allocate the in-memory output",
+            RecorderError::NoOutputFile                   => "no output file",
+            RecorderError::NotInMemory                    => "not an in-memory output",
+            RecorderError::HeaderWriting(..)              => "failed to write the output header",
+            RecorderError::FrameEncoding(..)              => "error encoding frame",
+            RecorderError::FrameWriting(..)               => "error writing frame",
+            RecorderError::TrailerWriting(..)             => "failed to write the output trailer",
+            RecorderError::FileClose(..)                  => "failed to close the output file",
+            RecorderError::WorkerStopped                  => "the background encoding thread stopped",
+            RecorderError::MultipleOutputs(..)            => "some outputs failed",
+            RecorderError::Io(..)                         => "I/O error"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            RecorderError::Io(ref err)      => Some(err),
+            RecorderError::NulByte(ref err) => Some(err),
+            _                               => None
+        }
+    }
+}
+
+impl From<io::Error> for RecorderError {
+    fn from(err: io::Error) -> RecorderError {
+        RecorderError::Io(err)
+    }
+}
+
+impl From<NulError> for RecorderError {
+    fn from(err: NulError) -> RecorderError {
+        RecorderError::NulByte(err)
+    }
+}
+
+// A file extension whose container usually holds the given encoder's output.
+fn suggested_extension(codec: &str) -> &'static str {
+    if codec.contains("vp8") || codec.contains("vp9") || codec.contains("vpx") {
+        ".webm"
+    }
+    else if codec.contains("264") || codec.contains("265") || codec.contains("hevc") {
+        ".mp4"
+    }
+    else if codec.contains("prores") {
+        ".mov"
+    }
+    else {
+        // Matroska holds nearly any codec.
+        ".mkv"
+    }
+}
+
+/// Retrieves the human-readable description of an FFmpeg error code using `av_strerror`.
+pub fn ffmpeg_error_string(code: i32) -> String {
+    let mut buf = [0 as c_char; 256];
+
+    unsafe {
+        if avutil::av_strerror(code, buf.as_mut_ptr(), buf.len() as u64) < 0 {
+            return format!("unknown error {}", code);
+        }
+
+        String::from_utf8_lossy(CStr::from_ptr(buf.as_ptr()).to_bytes()).into_owned()
+    }
+}
+
+/// Retrieves the name of a pixel format, e.g. "yuv420p".
+pub fn pix_fmt_name(pix_fmt: i32) -> String {
+    unsafe {
+        let name = avutil::av_get_pix_fmt_name(pix_fmt);
+
+        if name.is_null() {
+            return format!("unknown pixel format {}", pix_fmt);
+        }
+
+        String::from_utf8_lossy(CStr::from_ptr(name).to_bytes()).into_owned()
+    }
+}
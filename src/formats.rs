@@ -0,0 +1,61 @@
+//! Introspection of the container formats available in the linked FFmpeg.
+
+use std::ptr;
+use std::ffi::CStr;
+use libc::c_char;
+use avcodec;
+use avformat;
+
+/// Description of a container format able to hold video.
+#[derive(Clone, Debug)]
+pub struct FormatInfo {
+    /// Short name of the format, e.g. "mp4".
+    pub name:        String,
+    /// Descriptive name of the format, e.g. "MP4 (MPEG-4 Part 14)".
+    pub long_name:   String,
+    /// File extensions associated with the format, e.g. `["mp4"]`.
+    pub extensions:  Vec<String>,
+    /// Id of the video codec used by default with this format.
+    pub video_codec: i32
+}
+
+/// Lists the container formats the linked FFmpeg can write video into.
+pub fn available_formats() -> Vec<FormatInfo> {
+    ::register_all();
+
+    let mut res = Vec::new();
+
+    unsafe {
+        let mut fmt = avformat::av_oformat_next(ptr::null_mut());
+
+        while !fmt.is_null() {
+            if (*fmt).video_codec != avcodec::AV_CODEC_ID_NONE {
+                let extensions = to_string((*fmt).extensions);
+
+                res.push(FormatInfo {
+                    name:        to_string((*fmt).name),
+                    long_name:   to_string((*fmt).long_name),
+                    extensions:  extensions.split(',')
+                                           .filter(|e| !e.is_empty())
+                                           .map(|e| e.to_string())
+                                           .collect(),
+                    video_codec: (*fmt).video_codec as i32
+                });
+            }
+
+            fmt = avformat::av_oformat_next(fmt);
+        }
+    }
+
+    res
+}
+
+/// Converts a possibly null C string to a `String`.
+pub unsafe fn to_string(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    }
+    else {
+        String::from_utf8_lossy(CStr::from_ptr(s).to_bytes()).into_owned()
+    }
+}
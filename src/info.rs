@@ -0,0 +1,64 @@
+//! Introspection of the settings negotiated with FFmpeg.
+
+use Recorder;
+use formats::to_string;
+
+/// The parameters of the video stream, as resolved by the initialization.
+///
+/// They may differ from those requested: the pixel format falls back to one the encoder
+/// supports, the GOP structure is clamped to the encoder capabilities, and the muxer may impose
+/// its own stream time base.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamInfo {
+    /// The id of the codec, e.g. `avcodec::AV_CODEC_ID_H264`.
+    pub codec_id:         i32,
+    /// The name of the encoder, e.g. "libx264".
+    pub codec_name:       String,
+    /// The short name of the container format, e.g. "mp4".
+    pub format_name:      String,
+    /// The width of the video.
+    pub width:            usize,
+    /// The height of the video.
+    pub height:           usize,
+    /// The pixel format of the encoded images.
+    pub pix_fmt:          i32,
+    /// The time base of the encoder, i-e, the nominal frame duration, in seconds.
+    pub time_base:        (usize, usize),
+    /// The time base of the timestamps stored in the container, in seconds.
+    pub stream_time_base: (usize, usize),
+    /// The number of pictures in a group of pictures.
+    pub gop_size:         usize,
+    /// The maximum number of B-frames between non-B-frames.
+    pub max_b_frames:     usize
+}
+
+impl Recorder {
+    /// The parameters negotiated for the video stream, e.g. to display "recording as H.264
+    /// 1920x1080", or `None` if the recorder is not initialized yet, or already finished.
+    ///
+    /// Call `init` first to know them before the first snapshot.
+    pub fn stream_info(&self) -> Option<StreamInfo> {
+        if !self.initialized || self.finalized || self.context.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let context   = self.context;
+            let time_base = (*context).time_base;
+            let stream_tb = (*self.video_st).time_base;
+
+            Some(StreamInfo {
+                codec_id:         (*context).codec_id as i32,
+                codec_name:       to_string((*(*context).codec).name),
+                format_name:      to_string((*(*self.format_context).oformat).name),
+                width:            (*context).width  as usize,
+                height:           (*context).height as usize,
+                pix_fmt:          (*context).pix_fmt,
+                time_base:        (time_base.num as usize, time_base.den as usize),
+                stream_time_base: (stream_tb.num as usize, stream_tb.den as usize),
+                gop_size:         (*context).gop_size as usize,
+                max_b_frames:     (*context).max_b_frames as usize
+            })
+        }
+    }
+}
@@ -1,9 +1,14 @@
 /*!
  * Video recorder for the `kiss3d` graphics engine.
+ *
+ * The capture of kiss3d windows is enabled by the default `kiss3d` cargo feature. Without it,
+ * e.g. for headless captures, the crate does not depend on kiss3d and its graphics stack, and
+ * only records images given as raw buffers, with `snap_rgb` and the like.
  */
 
 #![crate_type = "lib"]
 #![feature(convert)]
+#![cfg_attr(test, feature(test))]
 #![deny(non_camel_case_types)]
 #![deny(unused_parens)]
 #![deny(non_upper_case_globals)]
@@ -20,25 +25,95 @@ extern crate avcodec55 as avcodec;
 extern crate avutil52 as avutil;
 extern crate avformat55 as avformat;
 extern crate swscale2 as swscale;
+#[cfg(feature = "kiss3d")]
 extern crate kiss3d;
+extern crate time;
+extern crate image;
+#[cfg(test)]
+extern crate test as bench; // `test` is the name of the unit test module.
 
 // inspired by the muxing sample: http://ffmpeg.org/doxygen/trunk/muxing_8c-source.html
 
-use libc::c_void;
 use swscale::Struct_SwsContext;
 use avcodec::{AVCodec, AVCodecContext, AVPacket};
-use avformat::{AVFormatContext, AVStream};
-use avutil::{AVFrame, Struct_AVRational};
+use avformat::{AVFormatContext, AVOutputFormat, AVStream};
+use avutil::{AVDictionary, AVFrame, Struct_AVRational};
 use std::ptr;
 use std::mem;
-use std::path::PathBuf;
-use std::ffi::{CString,OsStr};
-use std::iter::FromIterator;
+use std::cmp;
+use std::fs;
+use std::fmt;
+use std::ascii::AsciiExt;
+use std::path::{Path, PathBuf};
+use std::ffi::{CStr,CString,OsStr};
+use std::iter::{self, FromIterator};
 use std::sync::{Once, ONCE_INIT};
+#[cfg(feature = "kiss3d")]
 use kiss3d::window::Window;
+use error::ffmpeg_error_string;
+use memory::CustomOutput;
+use overlay::TimestampOverlay;
+use log::warn;
+
+pub use error::RecorderError;
+pub use builder::{RecorderBuilder, Preset, ProResProfile};
+pub use threaded::{ThreadedRecorder, Backpressure};
+#[cfg(feature = "kiss3d")]
+pub use screenshot::{screenshot, grab_rgb};
+pub use formats::{available_formats, FormatInfo};
+pub use log::{Level, LogCallback};
+pub use progress::{ProgressInfo, ProgressCallback, SnapOutcome};
+pub use twopass::TwoPassRecorder;
+#[cfg(feature = "kiss3d")]
+pub use session::{record_session, Control};
+pub use info::StreamInfo;
+pub use multi::MultiRecorder;
+pub use source::{FrameSource, IterSource};
+
+mod error;
+mod builder;
+mod threaded;
+#[cfg(feature = "kiss3d")]
+mod screenshot;
+mod memory;
+mod formats;
+mod log;
+mod progress;
+mod twopass;
+mod sequence;
+#[cfg(feature = "kiss3d")]
+mod session;
+mod overlay;
+mod info;
+mod multi;
+mod source;
+mod stereo;
 
 static mut avformat_init: Once = ONCE_INIT;
 
+// The RGB48 pixel format in the endianness of the target.
+#[cfg(target_endian = "little")]
+const PIX_FMT_RGB48_NATIVE: i32 = avutil::PIX_FMT_RGB48LE;
+#[cfg(target_endian = "big")]
+const PIX_FMT_RGB48_NATIVE: i32 = avutil::PIX_FMT_RGB48BE;
+
+// Alignment, in bytes, of the rows of the captured images handed to the scaler.
+const INPUT_ROW_ALIGN: usize = 16;
+
+// Makes the encoder compute the squared error of each frame.
+const CODEC_FLAG_PSNR: i32 = 0x8000; // XXX: this should be defined by the bindings.
+
+// Registers the FFmpeg muxers and codecs, once per process.
+fn register_all() {
+    unsafe {
+        avformat_init.call_once(|| {
+            avformat::av_register_all();
+            // Needed to stream to network URLs.
+            let _ = avformat::avformat_network_init();
+        });
+    }
+}
+
 /// OpenGL rendering video recorder.
 ///
 /// Use this to make a video of your crazy 3D scene.
@@ -47,45 +122,142 @@ pub struct Recorder {
     frame_buf:        Vec<u8>,
     curr_frame_index: usize,
     initialized:      bool,
+    finalized:        bool,
+    paused:           bool,
+    paused_at:        Option<f64>,
+    pace_realtime:    bool,
+    quality_stats:    bool,
+    force_keyframe:   bool,
+    // Whether frames were placed at explicit times, by `snap_at` or real-time pacing.
+    irregular_pts:    bool,
+    capture_every:    usize,
+    capture_calls:    usize,
+    frame_limit:      Option<usize>,
+    finalize_at_limit: bool,
+    realtime_start:   Option<f64>,
+    bytes_written:    u64,
+    packets_written:  usize,
+    // The first failure to write a packet, which leaves the output incomplete.
+    write_error:      Option<(i32, String)>,
+    sync_every:       Option<usize>,
+    progress_callback: Option<ProgressCallback>,
     bit_rate:         usize,
+    crf:              Option<f64>,
+    qscale:           Option<f64>,
     width:            usize,
     height:           usize,
     time_base:        (usize, usize),
+    stream_time_base: (usize, usize),
     gop_size:         usize,
+    // The keyframe interval in seconds, replacing `gop_size` if set.
+    keyframe_interval: Option<f64>,
     max_b_frames:     usize,
-    pix_fmt:          i32,
+    pix_fmt:          Option<i32>,
+    scale_flags:      i32,
+    color_range:      Option<i32>,
+    colorspace:       Option<i32>,
+    color_primaries:  Option<i32>,
+    encoder_threads:  Option<usize>,
+    pass_flags:       i32,
+    stats_in:         Option<CString>,
+    stats_out:        String,
+    codec_name:       Option<String>,
+    codec_options:    Vec<(CString, CString)>,
+    unused_options:   Vec<String>,
+    metadata:         Vec<(CString, CString)>,
+    capture_region:   Option<(usize, usize, usize, usize)>,
+    timestamp_overlay: Option<TimestampOverlay>,
+    stereo:           bool,
+    // The size and pixel format of the last captured image.
+    input_format:     Option<(usize, usize, i32)>,
     tmp_frame:        *mut AVFrame,
     frame:            *mut AVFrame,
+    // Reused for every encoded frame; only its payload is released after each write.
+    packet:           AVPacket,
     context:          *mut AVCodecContext,
     format_context:   *mut AVFormatContext,
     video_st:         *mut AVStream,
+    // Created by the first conversion, once the input size is known, and rebuilt by
+    // `sws_getCachedContext` when it changes; `init` creates none. Owned by the recorder, and
+    // freed along with the other contexts by `finalize`, `reset` or the destructor.
     scale_context:    *mut Struct_SwsContext,
-    path:             PathBuf
+    path:             PathBuf,
+    segment_duration: Option<f64>,
+    segment_index:    usize,
+    segment_base:     PathBuf,
+    // The frames of the previous segments, which count towards the frame limit.
+    segment_frames:   usize,
+    format_name:      Option<String>,
+    fallback_format:  Option<String>,
+    // The in-memory buffer or writer replacing the output file, if any.
+    custom_output:    Option<Box<CustomOutput>>
 }
 
 impl Recorder {
     /// Creates a new video recorder.
     ///
+    /// The width and height are those of the output video. Captured images of a different size
+    /// are rescaled, so a 4K window can be recorded into a 1080p video.
+    ///
+    /// The container format is guessed from the extension of the path, falling back to MPEG if it
+    /// cannot be (see `RecorderBuilder::fallback_format`). A ".gif" path produces an animated GIF
+    /// using a fixed 256-color palette; keep such clips short, and prefer a frame rate dividing
+    /// 100 (e.g. 25 or 50 fps) since GIF timestamps are in hundredths of a second.
+    ///
+    /// The path may also be the URL of a live stream. "rtmp://" and "rtmps://" URLs are streamed
+    /// in FLV, "udp://", "tcp://" and "srt://" ones in MPEG-TS. This requires an FFmpeg built
+    /// with networking and the corresponding protocols and muxers (e.g. `--enable-network`,
+    /// `--enable-protocol=rtmp`, `--enable-muxer=flv`, or `--enable-librtmp`). A connection
+    /// failure makes the initialization fail with `RecorderError::FileOpen`. Pair live streams
+    /// with `set_pace_realtime`, so that the stream advances with the wall clock.
+    ///
+    /// Named pipes (FIFOs) can be used as well, for another process to read the video while it is
+    /// recorded. Such outputs are not seekable: MP4 and MOV files are then written as fragmented
+    /// MP4, which most players and tools read fine.
+    ///
     /// # Arguments:
     /// * `path`   - path to the output file.
-    /// * `width`  - width of the recorded video.
-    /// * `height` - height of the recorded video.
+    /// * `width`  - width of the recorded video. Rounded up to a multiple of two. If zero, the
+    ///              size of the first captured image is used.
+    /// * `height` - height of the recorded video. Rounded up to a multiple of two. If zero, the
+    ///              size of the first captured image is used.
     pub fn new<P: ?Sized + AsRef<OsStr>>(path: &P, width: usize, height: usize) -> Recorder {
-        Recorder::new_with_params(path, width, height, None, None, None, None, None)
+        RecorderBuilder::new().build(path, width, height)
+    }
+
+    /// Creates a new video recorder with the size of the window.
+    ///
+    /// The size is that of the window framebuffer, i-e, of the images captured by `snap`. On
+    /// HiDPI displays, it is larger than the logical size reported by `Window::width` and
+    /// `Window::height`, e.g. twice as large with a scale factor of 2. The window is captured once
+    /// to measure it. Resizing the window later does not change the size of the video.
+    ///
+    /// # Arguments:
+    /// * `window` - the window to be recorded.
+    /// * `path`   - path to the output file.
+    #[cfg(feature = "kiss3d")]
+    pub fn for_window<P: ?Sized + AsRef<OsStr>>(window: &Window, path: &P) -> Recorder {
+        RecorderBuilder::new().build_for_window(window, path)
     }
 
     /// Creates a new video recorder with custom recording parameters.
     ///
+    /// See `RecorderBuilder` for a more readable way of setting these parameters.
+    ///
     /// # Arguments:
     /// * `path`         - path to the output file.
-    /// * `width`        - width of the recorded video.
-    /// * `height`       - height of the recorded video.
+    /// * `width`        - width of the recorded video. Rounded up to a multiple of two. If zero,
+    ///                    the size of the first captured image is used.
+    /// * `height`       - height of the recorded video. Rounded up to a multiple of two. If zero,
+    ///                    the size of the first captured image is used.
     /// * `bit_rate`     - the average bit rate. Default value: 400000.
     /// * `time_base`    - this is the fundamental unit of time (in seconds) in terms of which
     ///                    frame timestamps are represented. Default value: (1, 60), i-e, 60fps.
     /// * `gop_size`     - the number of pictures in a group of pictures. Default value: 10.
     /// * `max_b_frames` - maximum number of B-frames between non-B-frames. Default value: 1.
-    /// * `pix_fmt`      - pixel format. Default value: `avutil::PIX_FMT_YUV420P`.
+    /// * `pix_fmt`      - pixel format, to which the captured images are converted. Default
+    ///                    value: `avutil::PIX_FMT_YUV420P` if the encoder supports it, otherwise
+    ///                    the first format it supports. See `RecorderBuilder::pix_fmt`.
     pub fn new_with_params<P: ?Sized + AsRef<OsStr>>(path:         &P,
                                                      width:        usize,
                                                      height:       usize,
@@ -95,340 +267,3022 @@ impl Recorder {
                                                      max_b_frames: Option<usize>,
                                                      pix_fmt:      Option<i32>)
                                                      -> Recorder {
-        unsafe {
-            avformat_init.call_once(|| {
-                avformat::av_register_all();
-            });
+        let mut builder = RecorderBuilder::new();
+
+        if let Some(bit_rate) = bit_rate {
+            builder = builder.bit_rate(bit_rate);
         }
 
-        let bit_rate     = bit_rate.unwrap_or(400000); // FIXME
-        let time_base    = time_base.unwrap_or((1, 60));
-        let gop_size     = gop_size.unwrap_or(10);
-        let max_b_frames = max_b_frames.unwrap_or(1);
-        let pix_fmt      = pix_fmt.unwrap_or(avutil::PIX_FMT_YUV420P);
-        // width and height must be a multiple of two.
-        let width        = if width  % 2 == 0 { width }  else { width + 1 };
-        let height       = if height % 2 == 0 { height } else { height + 1 };
-
-        Recorder {
-            initialized:      false,
-            curr_frame_index: 0,
-            bit_rate:         bit_rate,
-            width:            width,
-            height:           height,
-            time_base:        time_base,
-            gop_size:         gop_size,
-            max_b_frames:     max_b_frames,
-            pix_fmt:          pix_fmt,
-            frame:            ptr::null_mut(),
-            tmp_frame:        ptr::null_mut(),
-            context:          ptr::null_mut(),
-            scale_context:    ptr::null_mut(),
-            format_context:   ptr::null_mut(),
-            video_st:         ptr::null_mut(),
-            path:             PathBuf::from(path),
-            frame_buf:        Vec::new(),
-            tmp_frame_buf:    Vec::new()
-        }
-    }
-                            
-    /// Captures an image from the window and adds it to the current video.
-    pub fn snap(&mut self, window: &Window) {
-        self.init();
+        if let Some(time_base) = time_base {
+            builder = builder.time_base(time_base);
+        }
 
-        let mut pkt: AVPacket = unsafe { mem::uninitialized() };
+        if let Some(gop_size) = gop_size {
+            builder = builder.gop_size(gop_size);
+        }
 
-        unsafe {
-            avcodec::av_init_packet(&mut pkt);
+        if let Some(max_b_frames) = max_b_frames {
+            builder = builder.max_b_frames(max_b_frames);
         }
 
-        pkt.data = ptr::null_mut();  // packet data will be allocated by the encoder
-        pkt.size = 0;
+        if let Some(pix_fmt) = pix_fmt {
+            builder = builder.pix_fmt(pix_fmt);
+        }
 
-        /*
-         *
-         * Fill the snapshot frame.
-         *
-         */
-        window.snap(&mut self.tmp_frame_buf);
+        builder.build(path, width, height)
+    }
 
+    /// Selects the encoder by name, e.g. "libx264" or "libvpx-vp9".
+    ///
+    /// The encoder must be supported by the output container, e.g. a ".webm" path for
+    /// "libvpx-vp9" or a ".mp4" path for "libx264"; otherwise initialization fails with
+    /// `RecorderError::IncompatibleCodecContainer`. By default, the default encoder of the
+    /// output container is used. This fails with `RecorderError::AlreadyInitialized` once the
+    /// recorder is initialized. If the named encoder is not compiled into the linked FFmpeg,
+    /// initialization fails with `RecorderError::EncoderNotFound`.
+    ///
+    /// Hardware encoders like "h264_nvenc" are supported as long as they accept frames from
+    /// system memory. If no suitable GPU or driver is available, initialization fails with
+    /// `RecorderError::HardwareEncoderUnavailable`.
+    pub fn with_codec_name(&mut self, name: &str) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
 
-        let win_width  = window.width() as i32;
-        let win_height = window.height() as i32;
+        self.codec_name = Some(name.to_string());
 
-        vflip(self.tmp_frame_buf.as_mut_slice(), win_width as usize * 3, win_height as usize);
+        Ok(())
+    }
 
-        unsafe {
-            (*self.frame).pts += avutil::av_rescale_q(1, (*self.context).time_base, (*self.video_st).time_base);
-            self.curr_frame_index = self.curr_frame_index + 1;
+    /// Sets a private option of the encoder, e.g. "preset" to "ultrafast" for libx264.
+    ///
+    /// Options are passed to the encoder when the recorder is initialized; setting one afterwards
+    /// fails with `RecorderError::AlreadyInitialized`. Options the encoder does not recognize are
+    /// reported by `unused_codec_options`. A `key` or `value` containing a nul byte fails with
+    /// `RecorderError::NulByte`.
+    pub fn set_codec_option(&mut self, key: &str, value: &str) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        let key   = try!(CString::new(key));
+        let value = try!(CString::new(value));
+
+        self.codec_options.push((key, value));
+
+        Ok(())
+    }
+
+    /// Selects an encoder preset. This is the same as setting the "preset" codec option, and
+    /// fails likewise with `RecorderError::AlreadyInitialized` after initialization.
+    pub fn set_preset(&mut self, preset: Preset) -> Result<(), RecorderError> {
+        self.set_codec_option("preset", preset.name())
+    }
+
+    /// Sets a metadata tag of the output container, e.g. "title", "artist" or "comment".
+    ///
+    /// Tags are written when the recorder is initialized; setting one afterwards fails with
+    /// `RecorderError::AlreadyInitialized`. A `key` or `value` containing a nul byte fails with
+    /// `RecorderError::NulByte`.
+    pub fn set_metadata(&mut self, key: &str, value: &str) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        let key   = try!(CString::new(key));
+        let value = try!(CString::new(value));
+
+        self.metadata.push((key, value));
+
+        Ok(())
+    }
+
+    /// Changes the average bit rate, possibly in the middle of the recording.
+    ///
+    /// Before initialization, this simply switches the recorder to bit rate mode, cancelling the
+    /// constant quality or quantizer mode if it was selected. Afterwards, the new bit rate is
+    /// handed to the running encoder, and fails with `RecorderError::ConstantQualityMode` if the
+    /// recording uses one of those modes. Not all encoders honor mid-stream changes: some ignore
+    /// them altogether, and encoders with a strict rate control may only apply them from the
+    /// next group of pictures on.
+    pub fn set_bitrate(&mut self, bitrate: usize) -> Result<(), RecorderError> {
+        if !self.initialized {
+            self.bit_rate = bitrate;
+            self.crf      = None;
+            self.qscale   = None;
+
+            return Ok(());
         }
 
+        try!(self.check_not_finalized());
+
+        if self.crf.is_some() || self.qscale.is_some() {
+            return Err(RecorderError::ConstantQualityMode);
+        }
+
+        self.bit_rate = bitrate;
+
         unsafe {
+            (*self.context).bit_rate = bitrate as i32;
+        }
 
-            (*self.tmp_frame).width  = win_width;
-            (*self.tmp_frame).height = win_height;
+        Ok(())
+    }
 
-            let _ = avcodec::avpicture_fill(self.tmp_frame as *mut avcodec::AVPicture,
-                                            self.tmp_frame_buf.get(0).unwrap(),
-                                            avutil::PIX_FMT_RGB24,
-                                            win_width,
-                                            win_height);
+    /// Sets the number of pictures in a group of pictures, i-e, the maximum interval between
+    /// keyframes, in frames.
+    ///
+    /// A GOP size of 0 makes every frame a keyframe, as with `RecorderBuilder::gop_size`, and the
+    /// GOP size is adjusted to the encoder at initialization in the same way. A GOP longer than
+    /// the frame limit set with `set_frame_limit` leaves a single keyframe, and is logged as a
+    /// warning. This cancels any keyframe interval set in seconds, and fails with
+    /// `RecorderError::AlreadyInitialized` after initialization.
+    pub fn set_gop_size(&mut self, gop_size: usize) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        self.gop_size          = gop_size;
+        self.keyframe_interval = None;
+
+        self.check_gop_size_against_limit();
+
+        Ok(())
+    }
+
+    // Warns when the GOP is longer than the whole recording, as limited by `set_frame_limit`.
+    fn check_gop_size_against_limit(&self) {
+        if let (Some(limit), None) = (self.frame_limit, self.keyframe_interval) {
+            if self.gop_size > limit {
+                warn(&format!("a GOP size of {} exceeds the {} frames of the recording: only the \
+                               first frame will be a keyframe.", self.gop_size, limit));
+            }
+        }
+    }
+
+    /// Switches the encoder to constant quantizer mode, e.g. for archival at a fixed quality
+    /// independent of any bit rate target.
+    ///
+    /// Every frame is then quantized with the scale `q`, from 1 (best quality, biggest files) to
+    /// 31 (worst). This is honored by the FFmpeg native encoders of the MPEG family, like
+    /// "mpeg1video", "mpeg2video", "mpeg4", "mjpeg" and "h263"; encoders like libx264 or libvpx
+    /// ignore it, and offer the "qp" or "crf" codec options instead. For intra-only archival,
+    /// combine it with a `gop_size` of 1. This cancels the bit rate and constant quality modes,
+    /// and fails with `RecorderError::AlreadyInitialized` after initialization, or with
+    /// `RecorderError::InvalidArgument` if `q` is not positive.
+    pub fn set_qscale(&mut self, q: f64) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        if !(q > 0.0) {
+            return Err(RecorderError::InvalidArgument(
+                format!("the quantizer scale must be positive, got {}", q)));
         }
 
-        /*
-         * Convert the snapshot frame to the right format for the destination frame.
-         */
-        unsafe {
-            self.scale_context = swscale::sws_getCachedContext(
-                self.scale_context, win_width, win_height, avutil::PIX_FMT_RGB24,
-                self.width as i32, self.height as i32, avutil::PIX_FMT_YUV420P,
-                swscale::SWS_BICUBIC as i32, ptr::null_mut(), ptr::null_mut(), ptr::null()
-                );
+        self.qscale = Some(q);
+        self.crf    = None;
 
-            let _ = swscale::sws_scale(self.scale_context,
-                                       mem::transmute(&(*self.tmp_frame).data[0]), &(*self.tmp_frame).linesize[0],
-                                       0, win_height,
-                                       mem::transmute(&(*self.frame).data[0]), &(*self.frame).linesize[0]);
+        Ok(())
+    }
+
+    /// Whether the recorder is initialized, either explicitly with `init` or by the first
+    /// snapshot.
+    ///
+    /// Settings that configure the encoder or the container can only be changed before that.
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    // Guards the methods using the encoder or the output, which are freed once finished.
+    fn check_not_finalized(&self) -> Result<(), RecorderError> {
+        if self.finalized {
+            Err(RecorderError::Closed)
         }
+        else {
+            Ok(())
+        }
+    }
 
+    // Guards the setters that only take effect at initialization.
+    fn check_not_initialized(&self) -> Result<(), RecorderError> {
+        if self.initialized {
+            Err(RecorderError::AlreadyInitialized)
+        }
+        else {
+            Ok(())
+        }
+    }
 
-        // Encode the image.
+    /// The codec options the encoder did not recognize.
+    ///
+    /// This is empty until the recorder is initialized. A non-empty list usually indicates a typo
+    /// in an option name given to `set_codec_option`.
+    pub fn unused_codec_options(&self) -> &[String] {
+        &self.unused_options[..]
+    }
 
-        let mut got_output = 0;
-        let ret;
+    /// The path of the output file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 
-        unsafe {
-            ret = avcodec::avcodec_encode_video2(self.context,
-                                                 &mut pkt,
-                                                 self.frame,
-                                                 &mut got_output);
+    /// The number of frames captured so far, into the current segment of a segmented recording.
+    pub fn frame_count(&self) -> usize {
+        self.curr_frame_index
+    }
+
+    /// The total size of the encoded packets written so far, in bytes.
+    ///
+    /// Unlike the size of the output file, this is up to date even though FFmpeg buffers its
+    /// writes, but does not include the container overhead. Divide by `duration_secs` for the
+    /// effective bit rate. This starts over with each new file, e.g. after `reset`.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// The approximate duration of the video recorded so far, in seconds.
+    pub fn duration_secs(&self) -> f64 {
+        let (tnum, tdenum) = self.time_base;
+
+        (self.curr_frame_index * self.capture_every) as f64 * tnum as f64 / tdenum as f64
+    }
+
+    /// Pauses the recording.
+    ///
+    /// While paused, snapshots are ignored and do not advance the video timeline: the paused time
+    /// is simply removed from the video, and playback jumps directly from the last frame before
+    /// the pause to the first frame after it.
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.paused_at = Some(time::precise_time_s());
         }
 
-        if ret < 0 {
-            panic!("Error encoding frame.");
+        self.paused = true;
+    }
+
+    /// Resumes a paused recording.
+    pub fn resume(&mut self) {
+        // With real-time pacing, the paused time must not count as elapsed.
+        if let (Some(start), Some(paused_at)) = (self.realtime_start, self.paused_at) {
+            self.realtime_start = Some(start + time::precise_time_s() - paused_at);
         }
 
-        if got_output != 0 {
-            unsafe {
-                let _ = avformat::av_interleaved_write_frame(self.format_context, &mut pkt);
-                avcodec::av_free_packet(&mut pkt);
-            }
+        self.paused    = false;
+        self.paused_at = None;
+    }
+
+    /// Encodes only one snapshot out of `n`, e.g. 5 to record a 144fps render loop at 28.8fps.
+    ///
+    /// The other calls to `snap` and its variants return immediately, without even reading the
+    /// window. Each encoded frame lasts `n` frames of the time base, so the playback speed stays
+    /// right. Keep the time base at the rate of the render loop, e.g. with `fps(144.0)`. This is
+    /// deterministic, unlike real-time pacing, so it suits fixed-step simulations. `snap_for` and
+    /// `snap_at` always encode. Panics if `n` is 0.
+    pub fn set_capture_every_nth(&mut self, n: usize) {
+        assert!(n > 0, "At least one snapshot out of n must be captured.");
+
+        self.capture_every = n;
+        self.capture_calls = 0;
+    }
+
+    // Counts a snapshot, and tells whether it must be skipped because the recording is paused or
+    // complete, or only every nth snapshot is captured.
+    fn skips_capture(&mut self) -> bool {
+        if self.paused || self.is_complete() {
+            return true;
         }
+
+        let skip = self.capture_calls % self.capture_every != 0;
+
+        self.capture_calls = self.capture_calls + 1;
+
+        skip
     }
 
-    /// Initializes the recorder.
+    // The duration of a frame, in the codec time base.
+    fn pts_step(&self) -> i64 {
+        self.capture_every as i64
+    }
+
+    // The time of the next frame, in seconds.
+    unsafe fn next_pts_secs(&self) -> f64 {
+        let time_base = (*self.context).time_base;
+
+        (*self.frame).pts as f64 * time_base.num as f64 / time_base.den as f64
+    }
+
+    /// Enables or disables real-time pacing.
     ///
-    /// This is automatically called when the first snapshot is made. Call this explicitly if you
-    /// do not want the extra time overhead when the first snapshot is made.
-    pub fn init(&mut self) {
-        if self.initialized {
-            return;
+    /// By default, each snapshot advances the video by exactly one frame, so a render loop faster
+    /// than the frame rate produces a video playing too fast. With real-time pacing, snapshots are
+    /// placed according to the wall-clock time elapsed since the first one instead, rounded down
+    /// to a whole frame; a snapshot taken less than one frame interval after the previous one is
+    /// skipped. The playback duration then matches the real duration of the recording, minus
+    /// the paused time.
+    pub fn set_pace_realtime(&mut self, enabled: bool) {
+        self.pace_realtime = enabled;
+    }
+
+    /// Requests the next captured frame to be a keyframe, e.g. to make it a seek point or a
+    /// chapter start.
+    ///
+    /// This applies to the next frame only, regardless of `gop_size`. It is a hint: some encoders
+    /// may ignore it.
+    pub fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    /// Whether the recording is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops the recording after `n` frames, e.g. to make fixed-length clips for tests or
+    /// benchmarks.
+    ///
+    /// Once `n` frames are captured, the recording is complete: further calls to `snap` and its
+    /// variants return immediately, without writing anything, even if the recorder was finalized
+    /// at the limit. `snap_for` stops repeating its image at the limit as well. The frames of all
+    /// the segments of a segmented recording count, while `frame_count` only counts those of the
+    /// current segment. The limit is kept by `reset` and `stop`, which start a new recording.
+    /// Panics if `n` is 0.
+    pub fn set_frame_limit(&mut self, n: usize) {
+        assert!(n > 0, "The frame limit must be at least 1.");
+
+        self.frame_limit = Some(n);
+
+        self.check_gop_size_against_limit();
+    }
+
+    /// Removes the limit set by `set_frame_limit`.
+    pub fn clear_frame_limit(&mut self) {
+        self.frame_limit = None;
+    }
+
+    /// Finalizes the recording as soon as the frame limit is reached, rather than on `finalize`
+    /// or on drop. Default: disabled.
+    ///
+    /// The snapshot reaching the limit then reports the errors of the finalization as well, so
+    /// the output file is complete once it returns successfully.
+    pub fn set_finalize_at_limit(&mut self, enabled: bool) {
+        self.finalize_at_limit = enabled;
+    }
+
+    /// Whether the frame limit set by `set_frame_limit` is reached.
+    pub fn is_complete(&self) -> bool {
+        match self.frame_limit {
+            Some(n) => self.segment_frames + self.curr_frame_index >= n,
+            None    => false
         }
-        
-        let path_str = self.path.as_os_str().to_cstring().unwrap();
+    }
 
-        unsafe {
-            // try to guess the container type from the path.
-            let mut fmt = ptr::null_mut();
-            
+    /// Restricts the window snapshots to a rectangle.
+    ///
+    /// The rectangle starts at the pixel (`x`, `y`) counted from the *top-left* corner of the
+    /// window, like usual window coordinates (the vertical flip of the OpenGL framebuffer is taken
+    /// care of). It is clamped to the window at each snapshot; if it ends up empty, `snap` fails
+    /// with `RecorderError::EmptyCaptureRegion`. The region is rescaled to the size of the video.
+    pub fn set_capture_region(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.capture_region = Some((x, y, width, height));
+    }
 
-            let _ = avformat::avformat_alloc_output_context2(&mut fmt, ptr::null_mut(), ptr::null(), path_str.as_ptr());
+    /// Captures the whole window again, after a call to `set_capture_region`.
+    pub fn clear_capture_region(&mut self) {
+        self.capture_region = None;
+    }
 
-            if self.format_context.is_null() {
-                // could not guess, default to MPEG
-                let mpeg = CString::new(&b"mpeg"[..]).unwrap();
-                
-                let _ = avformat::avformat_alloc_output_context2(&mut fmt, ptr::null_mut(), mpeg.as_ptr(), path_str.as_ptr());
-            }
+    /// Captures an image from the window and adds it to the current video.
+    ///
+    /// The window may be resized during the recording: the video keeps its size, and the images
+    /// are scaled to it, without preserving their aspect ratio.
+    ///
+    /// If the frame cannot be encoded or written, an error is returned and the frame is skipped;
+    /// the recording can continue with the next call. This includes
+    /// `RecorderError::IncompleteFrame`, when the window returns a partial image, which may
+    /// happen before it renders its first frame. If this is the first snapshot, errors from
+    /// the lazy call to `init` are returned as well. Once the recording is finished, this fails
+    /// with `RecorderError::Closed`.
+    ///
+    /// On success, the outcome tells whether a packet reached the output. Encoders using
+    /// B-frames or frame threading hold frames back, so the first snapshots usually write
+    /// nothing, and each later one writes the packet of an earlier frame.
+    #[cfg(feature = "kiss3d")]
+    pub fn snap(&mut self, window: &Window) -> Result<SnapOutcome, RecorderError> {
+        if self.skips_capture() {
+            return Ok(SnapOutcome::default());
+        }
 
-            self.format_context = fmt;
+        let (width, height) = try!(self.grab(window));
 
-            if self.format_context.is_null() {
-                panic!("Unable to create the output context.");
-            }
+        self.encode_tmp_frame(width, height, avutil::PIX_FMT_RGB24, 3)
+    }
 
-            let fmt = (*self.format_context).oformat;
+    // Grabs the window content, or the capture region, into `tmp_frame_buf`. Returns its size.
+    #[cfg(feature = "kiss3d")]
+    fn grab(&mut self, window: &Window) -> Result<(usize, usize), RecorderError> {
+        let (width, height) = try!(snap_window(window, &mut self.tmp_frame_buf));
 
-            if (*fmt).video_codec == avcodec::AV_CODEC_ID_NONE {
-                panic!("The selected output container does not support video encoding.")
-            }
+        match self.capture_region {
+            Some(region) => {
+                let (w, h) = crop_rgb(&mut self.tmp_frame_buf, width, height, region);
 
-            let codec: *mut AVCodec;
+                if w == 0 || h == 0 {
+                    return Err(RecorderError::EmptyCaptureRegion);
+                }
 
-            let ret: i32 = 0;
+                Ok((w, h))
+            },
+            None => Ok((width, height))
+        }
+    }
 
-            codec = avcodec::avcodec_find_encoder((*fmt).video_codec);
+    /// Adds an RGB image to the current video.
+    ///
+    /// `data` must be tightly packed RGB24, i-e, of length `width * height * 3`, with rows ordered
+    /// bottom to top as returned by OpenGL. The image is rescaled to the size of the video if
+    /// needed. Any size is accepted, odd ones included: only the video size is rounded up to a
+    /// multiple of two, and the image is then stretched by at most one pixel in each direction.
+    /// A buffer of another length fails with `RecorderError::IncompleteFrame`. Errors are
+    /// otherwise reported as for `snap`.
+    pub fn snap_rgb(&mut self, data: &[u8], width: usize, height: usize) -> Result<SnapOutcome, RecorderError> {
+        if data.len() != width * height * 3 {
+            return Err(RecorderError::IncompleteFrame(width * height * 3, data.len()));
+        }
 
-            if codec.is_null() {
-                panic!("Codec not found.");
-            }
+        if self.skips_capture() {
+            return Ok(SnapOutcome::default());
+        }
 
-            self.video_st = avformat::avformat_new_stream(self.format_context, codec);
+        self.tmp_frame_buf.clear();
+        self.tmp_frame_buf.extend(data.iter().cloned());
 
-            if self.video_st.is_null() {
-                panic!("Failed to allocate the video stream.");
-            }
+        self.encode_tmp_frame(width, height, avutil::PIX_FMT_RGB24, 3)
+    }
 
-            (*self.video_st).id = ((*self.format_context).nb_streams - 1) as i32;
+    /// Adds an RGBA image to the current video.
+    ///
+    /// `data` must be tightly packed RGBA, i-e, of length `width * height * 4`, with rows ordered
+    /// bottom to top as returned by OpenGL. The alpha channel is kept if the video pixel format
+    /// has one, e.g. with `RecorderBuilder::preserve_alpha`, and dropped otherwise. Errors are
+    /// reported as for `snap_rgb`.
+    pub fn snap_rgba(&mut self, data: &[u8], width: usize, height: usize) -> Result<SnapOutcome, RecorderError> {
+        if data.len() != width * height * 4 {
+            return Err(RecorderError::IncompleteFrame(width * height * 4, data.len()));
+        }
 
-            self.context = (*self.video_st).codec;
+        if self.skips_capture() {
+            return Ok(SnapOutcome::default());
+        }
 
-            let _ = avcodec::avcodec_get_context_defaults3(self.context, codec);
+        self.tmp_frame_buf.clear();
+        self.tmp_frame_buf.extend(data.iter().cloned());
 
-            if self.context.is_null() {
-                panic!("Could not allocate video codec context.");
-            }
+        self.encode_tmp_frame(width, height, avutil::PIX_FMT_RGBA, 4)
+    }
 
-            // sws scaling context
-            self.scale_context = swscale::sws_getContext(
-                self.width as i32, self.height as i32, avutil::PIX_FMT_RGB24,
-                self.width as i32, self.height as i32, (*fmt).video_codec as i32,
-                swscale::SWS_BICUBIC as i32, ptr::null_mut(), ptr::null_mut(), ptr::null());
+    /// Adds a 16-bit per channel RGB image to the current video, e.g. from an HDR framebuffer.
+    ///
+    /// `data` must be tightly packed RGB48 in native endianness, i-e, of length
+    /// `width * height * 3`, with rows ordered bottom to top as returned by OpenGL. To keep the
+    /// extra precision, select a high bit depth pixel format supported by the encoder, e.g.
+    /// `avutil::PIX_FMT_YUV420P10LE` for libx264 built with 10-bit support; unsupported formats
+    /// make the initialization fail with `RecorderError::UnsupportedPixelFormat`. Otherwise, the
+    /// image is reduced to the usual 8 bits per channel. Errors are reported as for `snap_rgb`.
+    pub fn snap_rgb48(&mut self, data: &[u16], width: usize, height: usize) -> Result<SnapOutcome, RecorderError> {
+        if data.len() != width * height * 3 {
+            return Err(RecorderError::IncompleteFrame(width * height * 3, data.len()));
+        }
 
-            // Put sample parameters.
-            (*self.context).bit_rate = self.bit_rate as i32;
+        if self.skips_capture() {
+            return Ok(SnapOutcome::default());
+        }
 
-            // Resolution must be a multiple of two.
-            (*self.context).width    = self.width  as i32;
-            (*self.context).height   = self.height as i32;
+        self.tmp_frame_buf.clear();
 
-            // frames per second.
-            let (tnum, tdenum)           = self.time_base;
-            (*self.context).time_base    = Struct_AVRational { num: tnum as i32, den: tdenum as i32 };
-            (*self.video_st).time_base   = (*self.context).time_base;
-            (*self.context).gop_size     = self.gop_size as i32;
-            (*self.context).max_b_frames = self.max_b_frames as i32;
-            (*self.context).pix_fmt      = self.pix_fmt;
+        for v in data.iter() {
+            let bytes: [u8; 2] = unsafe { mem::transmute(*v) };
 
-            if (*self.context).codec_id == avcodec::AV_CODEC_ID_MPEG1VIDEO {
-                // Needed to avoid using macroblocks in which some coeffs overflow.
-                // This does not happen with normal video, it just happens here as
-                // the motion of the chroma plane does not match the luma plane.
-                (*self.context).mb_decision = 2;
-            }
+            self.tmp_frame_buf.push(bytes[0]);
+            self.tmp_frame_buf.push(bytes[1]);
+        }
 
-            /*
-            if (*fmt).flags & avformat::AVFMT_GLOBALHEADER != 0 {
-                (*self.context).flags = (*self.context).flags | CODEC_FLAG_GLOBAL_HEADER;
-            }
-            */
+        self.encode_tmp_frame(width, height, PIX_FMT_RGB48_NATIVE, 6)
+    }
 
-            // Open the codec.
-            if avcodec::avcodec_open2(self.context, codec, ptr::null_mut()) < 0 {
-                panic!("Could not open the codec.");
-            }
+    /// Adds a depth buffer to the current video, visualized in grayscale.
+    ///
+    /// `depth` holds one depth buffer value in [0, 1] per pixel, i-e, is of length
+    /// `width * height`, with rows ordered bottom to top as returned by OpenGL. The values are
+    /// converted back to distances from the camera using the `near` and `far` planes of the
+    /// perspective projection that produced them, then mapped linearly so that the near plane is
+    /// black and the far plane white. Planes that do not satisfy `0 < near < far` fail with
+    /// `RecorderError::InvalidArgument`. Errors are otherwise reported as for `snap_rgb`.
+    pub fn snap_depth(&mut self, depth: &[f32], width: usize, height: usize, near: f32, far: f32)
+                      -> Result<SnapOutcome, RecorderError> {
+        if depth.len() != width * height {
+            return Err(RecorderError::IncompleteFrame(width * height, depth.len()));
+        }
 
-            /*
-             * Init the destination video frame.
-             */
-            self.frame = avcodec::avcodec_alloc_frame();
+        if !(0.0 < near && near < far) {
+            return Err(RecorderError::InvalidArgument(
+                format!("the depth planes must satisfy 0 < near < far, got {} and {}", near, far)));
+        }
 
-            if self.frame.is_null() {
-                panic!("Could not allocate the video frame.");
-            }
+        if self.skips_capture() {
+            return Ok(SnapOutcome::default());
+        }
 
-            (*self.frame).format = (*self.context).pix_fmt;
-            (*self.frame).width  = (*self.context).width;
-            (*self.frame).height = (*self.context).height;
-            (*self.frame).pts    = 0;
+        self.tmp_frame_buf.clear();
 
-            // alloc the buffer
-            let nframe_bytes = avcodec::avpicture_get_size(self.pix_fmt,
-                                                           self.width as i32,
-                                                           self.height as i32);
-            
-            let reps = std::iter::repeat(0u8).take(nframe_bytes as usize);
-            self.frame_buf = Vec::<u8>::from_iter(reps);
-            //self.frame_buf = Vec::from_elem(nframe_bytes as usize, 0u8);
+        for d in depth.iter() {
+            // Undo the perspective projection, from normalized device coordinates.
+            let z_ndc    = 2.0 * *d - 1.0;
+            let distance = 2.0 * near * far / (far + near - z_ndc * (far - near));
+            let gray     = (distance - near) / (far - near);
 
-            let _ = avcodec::avpicture_fill(self.frame as *mut avcodec::AVPicture,
-                                            self.frame_buf.get(0).unwrap(),
-                                            self.pix_fmt,
-                                            self.width as i32,
-                                            self.height as i32);
+            self.tmp_frame_buf.push((gray.max(0.0).min(1.0) * 255.0).round() as u8);
+        }
 
-            /*
-             * Init the temporary video frame.
-             */
-            self.tmp_frame = avcodec::avcodec_alloc_frame();
+        self.encode_tmp_frame(width, height, avutil::PIX_FMT_GRAY8, 1)
+    }
 
-            if self.tmp_frame.is_null() {
-                panic!("Could not allocate the video frame.");
-            }
+    // Flips, converts and encodes the image stored in `tmp_frame_buf`, of pixel format `src_fmt`.
+    fn encode_tmp_frame(&mut self, width: usize, height: usize, src_fmt: i32, bytes_per_pixel: usize)
+                        -> Result<SnapOutcome, RecorderError> {
+        if self.is_complete() {
+            return Ok(SnapOutcome::default());
+        }
 
-            (*self.frame).format = (*self.context).pix_fmt;
-            // the rest (width, height, data, linesize) are set at the moment of the snapshot.
+        try!(self.check_not_finalized());
 
-            // Open the output file.
-            let path_str = self.path.as_os_str().to_cstring().unwrap();
-            
-            static AVIO_FLAG_WRITE: i32 = 2; // XXX: this should be defined by the bindings.
-            if avformat::avio_open(&mut (*self.format_context).pb, path_str.as_ptr(), AVIO_FLAG_WRITE) < 0 {
-                panic!("Failed to open the output file.");
-            }
+        if self.paused {
+            return Ok(SnapOutcome::default());
+        }
 
-            if avformat::avformat_write_header(self.format_context, ptr::null_mut()) < 0 {
-                panic!("Failed to open the output file.");
-            }
+        try!(self.split_segment_if_due());
+        try!(self.init_with_input_size(width, height));
 
-            if ret < 0 {
-                panic!("Could not allocate raw picture buffer");
-            }
+        if self.pace_realtime && !self.pace() {
+            return Ok(SnapOutcome::default());
         }
 
-        self.initialized = true;
+        try!(self.convert_tmp_frame(width, height, src_fmt, bytes_per_pixel));
+        self.encode_frame()
     }
-}
 
-impl Drop for Recorder {
-    fn drop(&mut self) {
-        if self.initialized {
-            // Get the delayed frames.
-            let mut pkt:   AVPacket = unsafe { mem::uninitialized() };
-            let mut got_output = 1;
-            while got_output != 0 {
-                let ret;
-
-                unsafe {
-                    avcodec::av_init_packet(&mut pkt);
-                }
+    // The GOP size, derived from the keyframe interval if it is set.
+    fn effective_gop_size(&self) -> usize {
+        match self.keyframe_interval {
+            Some(secs) => {
+                let (tnum, tdenum) = self.time_base;
+                let frame_secs     = (self.capture_every * tnum) as f64 / tdenum as f64;
 
-                pkt.data = ptr::null_mut();  // packet data will be allocated by the encoder
-                pkt.size = 0;
+                cmp::max(1, (secs / frame_secs).round() as usize)
+            },
+            None => self.gop_size
+        }
+    }
 
-                unsafe {
-                    ret = avcodec::avcodec_encode_video2(self.context, &mut pkt, ptr::null(), &mut got_output);
-                }
+    // Sets the timestamp of the next frame from the wall-clock time. Returns `false` if the frame
+    // should be skipped because no frame interval elapsed since the previous one.
+    fn pace(&mut self) -> bool {
+        let now   = time::precise_time_s();
+        let start = match self.realtime_start {
+            Some(start) => start,
+            None        => { self.realtime_start = Some(now); now }
+        };
 
-                if ret < 0 {
-                    panic!("Error encoding frame.");
-                }
+        let (tnum, tdenum) = self.time_base;
+        let pts            = ((now - start) * tdenum as f64 / tnum as f64).floor() as i64;
 
-                if got_output != 0 {
-                    unsafe {
-                        let _ = avformat::av_interleaved_write_frame(self.format_context, &mut pkt);
-                        avcodec::av_free_packet(&mut pkt);
-                    }
-                }
-            }
+        unsafe {
+            // After each snapshot, the frame pts is advanced by one frame past the encoded one.
+            let last_pts = (*self.frame).pts - self.pts_step();
 
-            // Free things and stuffs.
-            unsafe {
-                let _ = avcodec::avcodec_close(self.context);
-                avutil::av_free(self.context as *mut c_void);
-                // avutil::av_freep((*self.frame).data[0] as *mut c_void);
-                avcodec::avcodec_free_frame(&mut self.frame);
-                avcodec::avcodec_free_frame(&mut self.tmp_frame);
+            if self.curr_frame_index > 0 && pts <= last_pts {
+                return false;
             }
+
+            (*self.frame).pts = pts;
         }
+
+        self.irregular_pts = true;
+
+        true
     }
-}
 
-fn vflip(vec: &mut [u8], width: usize, height: usize) {
-    for j in (0..height / 2) {
-        for i in (0..width) {
-            vec.swap((height - j - 1) * width + i, j * width + i);
+    // Flips the image stored in `tmp_frame_buf` and converts it into the destination frame.
+    fn convert_tmp_frame(&mut self, width: usize, height: usize, src_fmt: i32, bytes_per_pixel: usize)
+                         -> Result<(), RecorderError> {
+        /*
+         *
+         * Fill the snapshot frame.
+         *
+         */
+        let in_width  = width  as i32;
+        let in_height = height as i32;
+        let row_len   = width * bytes_per_pixel;
+        let stride    = round_up_to_multiple(row_len, INPUT_ROW_ALIGN);
+
+        // The input size changes when the window is resized. The video size cannot change, so the
+        // new images are scaled to it; the scaling context is rebuilt below, and the buffer is
+        // padded for the new size.
+        let input_changed = self.input_format != Some((width, height, src_fmt));
+
+        if let Some((prev_width, prev_height, prev_fmt)) = self.input_format {
+            if (prev_width, prev_height, prev_fmt) != (width, height, src_fmt) {
+                log::log(Level::Info, &format!("the input changed from {}x{} to {}x{}: scaling it to {}x{}.",
+                                               prev_width, prev_height, width, height,
+                                               self.width, self.height));
+            }
         }
-    }
 
+        self.input_format = Some((width, height, src_fmt));
+
+        // Catches images with padded rows, e.g. read with a pack alignment other than 1.
+        assert!(self.tmp_frame_buf.len() == row_len * height,
+                "The captured image is {} bytes long instead of the {} expected for {}x{} pixels.",
+                self.tmp_frame_buf.len(), row_len * height, width, height);
+
+        vflip(self.tmp_frame_buf.as_mut_slice(), row_len, height);
+
+        if let Some(ref overlay) = self.timestamp_overlay {
+            let secs = unsafe { self.next_pts_secs() };
+
+            overlay.draw(&mut self.tmp_frame_buf[..], width, height, src_fmt, self.curr_frame_index, secs);
+        }
+
+        // Odd sizes give rows that are not aligned the way the optimized scalers expect: they
+        // may then read past the end of each row, and of the buffer. Pad every row instead.
+        pad_rows(&mut self.tmp_frame_buf, row_len, height, stride);
+
+        debug_assert!(self.tmp_frame_buf.len() == stride * (height + 1));
+
+        unsafe {
+
+            (*self.tmp_frame).format = src_fmt;
+            (*self.tmp_frame).width  = in_width;
+            (*self.tmp_frame).height = in_height;
+
+            // Only the source frame changes format; the destination one is always in the
+            // encoder format.
+            if (*self.frame).format != (*self.context).pix_fmt {
+                return Err(RecorderError::UnsupportedPixelFormat((*self.frame).format,
+                                                                 vec![(*self.context).pix_fmt]));
+            }
+
+            let _ = avcodec::avpicture_fill(self.tmp_frame as *mut avcodec::AVPicture,
+                                            self.tmp_frame_buf.get(0).unwrap(),
+                                            src_fmt,
+                                            in_width,
+                                            in_height);
+
+            (*self.tmp_frame).linesize[0] = stride as i32;
+        }
+
+        /*
+         * Convert the snapshot frame to the right format and size for the destination frame.
+         * The format is the one negotiated with the encoder, e.g. YUV420P for most video codecs
+         * but RGB8 for GIF. The scaling context is created on the first snapshot, and recreated
+         * whenever the input size or pixel format changes.
+         * The whole image is converted in one slice: this version of swscale is single-threaded.
+         */
+        unsafe {
+            let prev_scale_context = self.scale_context;
+
+            self.scale_context = swscale::sws_getCachedContext(
+                self.scale_context, in_width, in_height, src_fmt,
+                self.width as i32, self.height as i32, (*self.context).pix_fmt,
+                self.scale_flags, ptr::null_mut(), ptr::null_mut(), ptr::null()
+                );
+
+            // A new context converts with the BT.601 limited-range matrix, whatever the stream
+            // is tagged with. The context is rebuilt whenever the input changes, possibly at the
+            // same address.
+            let rebuilt = self.scale_context != prev_scale_context || input_changed;
+
+            if rebuilt && is_yuv((*self.context).pix_fmt) {
+                let coefficients = swscale::sws_getCoefficients((*self.context).colorspace as i32);
+                let full_range   = ((*self.context).color_range == avcodec::AVCOL_RANGE_JPEG) as i32;
+
+                let _ = swscale::sws_setColorspaceDetails(self.scale_context,
+                                                          coefficients, 1,
+                                                          coefficients, full_range,
+                                                          0, 1 << 16, 1 << 16);
+            }
+
+            let _ = swscale::sws_scale(self.scale_context,
+                                       mem::transmute(&(*self.tmp_frame).data[0]), &(*self.tmp_frame).linesize[0],
+                                       0, in_height,
+                                       mem::transmute(&(*self.frame).data[0]), &(*self.frame).linesize[0]);
+        }
+
+        Ok(())
+    }
+
+    // Encodes the destination frame and writes the resulting packet, if any.
+    fn encode_frame(&mut self) -> Result<SnapOutcome, RecorderError> {
+        let pkt: *mut AVPacket = &mut self.packet;
+
+        // Encode the image.
+
+        let mut got_output = 0;
+        let ret;
+
+        unsafe {
+            if self.force_keyframe {
+                (*self.frame).pict_type = avutil::AV_PICTURE_TYPE_I;
+                (*self.frame).key_frame = 1;
+            }
+
+            ret = avcodec::avcodec_encode_video2(self.context,
+                                                 pkt,
+                                                 self.frame,
+                                                 &mut got_output);
+
+            // The request applies to a single frame, even if it failed to encode.
+            if self.force_keyframe {
+                (*self.frame).pict_type = avutil::AV_PICTURE_TYPE_NONE;
+                (*self.frame).key_frame = 0;
+                self.force_keyframe     = false;
+            }
+        }
+
+        if ret < 0 {
+            return Err(RecorderError::FrameEncoding(ret, ffmpeg_error_string(ret)));
+        }
+
+        // Advance the timestamp only once the frame is encoded, so that the first frame is at 0.
+        unsafe {
+            (*self.frame).pts += self.pts_step();
+            self.curr_frame_index = self.curr_frame_index + 1;
+        }
+
+        let outcome = if got_output == 0 {
+            // The encoder keeps the frame for now, e.g. to reorder it with the next ones.
+            SnapOutcome::default()
+        }
+        else {
+            self.collect_stats();
+
+            let bytes = try!(self.write_packet());
+
+            SnapOutcome {
+                packet_written: true,
+                bytes:          bytes
+            }
+        };
+
+        if self.finalize_at_limit && self.is_complete() {
+            try!(self.finish());
+        }
+
+        Ok(outcome)
+    }
+
+    // Writes the encoded packet to the output, and reports the progress. Returns the size of the
+    // packet.
+    fn write_packet(&mut self) -> Result<usize, RecorderError> {
+        let pkt: *mut AVPacket = &mut self.packet;
+        let ret;
+        let size;
+        let pts_secs;
+        let quality;
+
+        unsafe {
+            static AV_NOPTS_VALUE: i64 = std::i64::MIN; // XXX: this should be defined by the bindings.
+
+            // The encoder stamps the packets in the codec time base, the muxer expects the finer
+            // stream time base.
+            let codec_time_base = (*self.context).time_base;
+            let time_base       = (*self.video_st).time_base;
+
+            if (*pkt).pts != AV_NOPTS_VALUE {
+                (*pkt).pts = avutil::av_rescale_q((*pkt).pts, codec_time_base, time_base);
+            }
+
+            if (*pkt).dts != AV_NOPTS_VALUE {
+                (*pkt).dts = avutil::av_rescale_q((*pkt).dts, codec_time_base, time_base);
+            }
+
+            size     = (*pkt).size as usize;
+            pts_secs = (*pkt).pts as f64 * time_base.num as f64 / time_base.den as f64;
+            quality  = self.coded_frame_psnr();
+
+            // Every packet lasts one frame interval, as the timestamps advance in `encode_frame`.
+            // Some editors rely on explicit durations rather than on the next timestamp. Frames
+            // placed at explicit times last until the next one, which is not known yet, so their
+            // duration is left unset for the muxer to derive.
+            (*pkt).duration = if self.irregular_pts {
+                0
+            }
+            else {
+                avutil::av_rescale_q(self.pts_step(), codec_time_base, time_base) as i32
+            };
+
+            ret = avformat::av_interleaved_write_frame(self.format_context, pkt);
+            // Releases the payload but keeps the packet for the next frame.
+            avcodec::av_free_packet(pkt);
+        }
+
+        if ret < 0 {
+            let msg = ffmpeg_error_string(ret);
+
+            if self.write_error.is_none() {
+                self.write_error = Some((ret, msg.clone()));
+            }
+
+            return Err(RecorderError::FrameWriting(ret, msg));
+        }
+
+        self.bytes_written   = self.bytes_written + size as u64;
+        self.packets_written = self.packets_written + 1;
+
+        if let Some(n) = self.sync_every {
+            if self.packets_written % n == 0 {
+                try!(self.sync_output());
+            }
+        }
+
+        if let Some(ref mut callback) = self.progress_callback {
+            callback(ProgressInfo {
+                frame_index:   self.packets_written - 1,
+                pts_secs:      pts_secs,
+                bytes_written: self.bytes_written,
+                quality:       quality
+            });
+        }
+
+        Ok(size)
+    }
+
+    // The luma PSNR of the frame of the last encoded packet, in dB, if quality stats are enabled.
+    //
+    // The encoder accumulates the squared error of each plane in `coded_frame.error`, only if the
+    // PSNR flag is set: a null error otherwise says nothing of the quality.
+    unsafe fn coded_frame_psnr(&self) -> Option<f32> {
+        if !self.quality_stats || (*self.context).flags & CODEC_FLAG_PSNR == 0 {
+            return None;
+        }
+
+        let coded_frame = (*self.context).coded_frame;
+
+        if coded_frame.is_null() {
+            return None;
+        }
+
+        let pixels = (*self.context).width as f64 * (*self.context).height as f64;
+        let mse    = (*coded_frame).error[0] as f64 / pixels;
+
+        if mse == 0.0 {
+            Some(std::f32::INFINITY)
+        }
+        else {
+            Some((10.0 * (255.0 * 255.0 / mse).log10()) as f32)
+        }
+    }
+
+    /// Captures an image from the window and holds it in the current video for `secs` seconds.
+    ///
+    /// The image is converted only once, then encoded as many times as needed to fill the given
+    /// duration, rounded to a whole number of frames (at least one). Encoders compress such
+    /// repeated frames very efficiently. Errors are reported as for `snap`.
+    #[cfg(feature = "kiss3d")]
+    pub fn snap_for(&mut self, window: &Window, secs: f64) -> Result<SnapOutcome, RecorderError> {
+        if self.is_complete() {
+            return Ok(SnapOutcome::default());
+        }
+
+        try!(self.check_not_finalized());
+
+        if self.paused {
+            return Ok(SnapOutcome::default());
+        }
+
+        let (width, height) = try!(self.grab(window));
+
+        try!(self.split_segment_if_due());
+        try!(self.init_with_input_size(width, height));
+
+        try!(self.convert_tmp_frame(width, height, avutil::PIX_FMT_RGB24, 3));
+
+        let (tnum, tdenum) = self.time_base;
+        let step           = (self.capture_every * tnum) as f64 / tdenum as f64;
+        let nframes        = (secs / step).round().max(1.0) as usize;
+
+        let mut outcome = SnapOutcome::default();
+
+        for _ in 0 .. nframes {
+            if self.is_complete() {
+                break;
+            }
+
+            let frame_outcome = try!(self.encode_frame());
+
+            outcome.packet_written = outcome.packet_written || frame_outcome.packet_written;
+            outcome.bytes          = outcome.bytes + frame_outcome.bytes;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Captures an image from the window and adds it to the current video at the given time.
+    ///
+    /// Unlike `snap`, which advances the video by exactly one frame, this places the image at an
+    /// explicit timestamp, which suits variable frame rate captures, e.g. of simulations with
+    /// non-uniform time steps. Timestamps are in seconds from the start of the video, so the first
+    /// call should usually be at 0.0, and must be strictly increasing; otherwise this fails with
+    /// `RecorderError::NonMonotonicTimestamp`. They are rounded to the time base, i-e, to whole
+    /// frames of the frame rate set with `fps`: for fine-grained timestamps, use a high frame
+    /// rate, e.g. `fps(1000.0)` for millisecond precision. Two timestamps rounded to the same
+    /// frame fail as non-increasing. Errors are otherwise reported as for `snap`.
+    #[cfg(feature = "kiss3d")]
+    pub fn snap_at(&mut self, window: &Window, timestamp_secs: f64) -> Result<SnapOutcome, RecorderError> {
+        if self.is_complete() {
+            return Ok(SnapOutcome::default());
+        }
+
+        try!(self.check_not_finalized());
+
+        if self.paused {
+            return Ok(SnapOutcome::default());
+        }
+
+        let (width, height) = try!(self.grab(window));
+
+        try!(self.init_with_input_size(width, height));
+
+        unsafe {
+            let time_base = (*self.context).time_base;
+            let pts       = (timestamp_secs * time_base.den as f64 / time_base.num as f64).round() as i64;
+
+            // After each snapshot, the frame pts is advanced by one frame past the encoded one.
+            let last_pts = (*self.frame).pts - self.pts_step();
+
+            if pts < 0 || (self.curr_frame_index > 0 && pts <= last_pts) {
+                return Err(RecorderError::NonMonotonicTimestamp(timestamp_secs));
+            }
+
+            (*self.frame).pts = pts;
+        }
+
+        self.irregular_pts = true;
+
+        try!(self.convert_tmp_frame(width, height, avutil::PIX_FMT_RGB24, 3));
+        self.encode_frame()
+    }
+
+    /// Captures an image from the window and adds it to the current video, panicking on error.
+    ///
+    /// This is the behavior `snap` had before it returned a `Result`.
+    #[deprecated(note = "use `snap` and handle the returned `RecorderError` instead")]
+    #[cfg(feature = "kiss3d")]
+    pub fn snap_unwrap(&mut self, window: &Window) {
+        if let Err(e) = self.snap(window) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Initializes the recorder.
+    ///
+    /// This is automatically called when the first snapshot is made. Call this explicitly if you
+    /// do not want the extra time overhead when the first snapshot is made.
+    ///
+    /// Fails if the output container, the codec, or the output file could not be set up, or if the
+    /// size of the video is still unknown.
+    pub fn init(&mut self) -> Result<(), RecorderError> {
+        try!(self.check_not_finalized());
+
+        if self.initialized {
+            return Ok(());
+        }
+
+        if self.width == 0 || self.height == 0 {
+            return Err(RecorderError::InvalidDimensions(self.width, self.height));
+        }
+        
+        let path_str = match os_to_cstring(self.path.as_os_str()) {
+            Some(s) => s,
+            None    => return Err(RecorderError::InvalidPath(self.path.clone()))
+        };
+
+        let opened = unsafe { self.open_contexts(&path_str) };
+
+        if opened.is_err() {
+            // `snap` retries the initialization, so nothing may be left behind by a failure.
+            unsafe {
+                let _ = self.close_output();
+            }
+
+            self.free_contexts();
+        }
+
+        try!(opened);
+
+        self.initialized = true;
+
+        Ok(())
+    }
+
+    // Allocates the contexts and frames, opens the encoder and the output, and writes the
+    // header. On failure, whatever was allocated is left for the caller to free.
+    unsafe fn open_contexts(&mut self, path_str: &CString) -> Result<(), RecorderError> {
+        self.format_context = try!(self.alloc_output_context(path_str));
+
+        let fmt = (*self.format_context).oformat;
+
+        if (*fmt).video_codec == avcodec::AV_CODEC_ID_NONE {
+            return Err(RecorderError::NoVideoSupport);
+        }
+
+        let codec = try!(self.find_encoder(fmt));
+
+        try!(check_codec_container(fmt, codec));
+
+        self.video_st = avformat::avformat_new_stream(self.format_context, codec);
+
+        if self.video_st.is_null() {
+            return Err(RecorderError::StreamAllocation);
+        }
+
+        (*self.video_st).id = ((*self.format_context).nb_streams - 1) as i32;
+
+        self.context = (*self.video_st).codec;
+
+        let _ = avcodec::avcodec_get_context_defaults3(self.context, codec);
+
+        if self.context.is_null() {
+            return Err(RecorderError::ContextAllocation);
+        }
+
+        // Put sample parameters.
+        static CODEC_FLAG_QSCALE: i32 = 0x0002; // XXX: this should be defined by the bindings.
+        static FF_QP2LAMBDA:      f64 = 118.0;  // XXX: this should be defined by the bindings.
+
+        match (self.qscale, self.crf) {
+            (Some(q), _) => {
+                (*self.context).bit_rate       = 0;
+                (*self.context).flags          = (*self.context).flags | CODEC_FLAG_QSCALE;
+                (*self.context).global_quality = (q * FF_QP2LAMBDA).round() as i32;
+            },
+            (None, Some(crf)) => {
+                // A nonzero bit rate would make the encoder target it instead of the quality.
+                (*self.context).bit_rate = 0;
+
+                let name  = CString::new(&b"crf"[..]).unwrap();
+                let value = CString::new(crf.to_string()).unwrap();
+                let ret   = avutil::av_opt_set((*self.context).priv_data, name.as_ptr(), value.as_ptr(), 0);
+
+                if ret < 0 {
+                    return Err(RecorderError::CodecOption("crf".to_string(), ret));
+                }
+            },
+            (None, None) => (*self.context).bit_rate = self.bit_rate as i32
+        }
+
+        // Resolution must be a multiple of two.
+        (*self.context).width    = self.width  as i32;
+        (*self.context).height   = self.height as i32;
+
+        // frames per second.
+        let (tnum, tdenum)           = self.time_base;
+        (*self.context).time_base    = Struct_AVRational { num: tnum as i32, den: tdenum as i32 };
+        // Frame timestamps are expressed in the codec time base, as the encoders expect; the
+        // packets are rescaled to the finer stream time base (the 90kHz MPEG clock by default)
+        // when written. The muxer may still change it.
+        let (snum, sdenum)           = self.stream_time_base;
+        (*self.video_st).time_base   = Struct_AVRational { num: snum as i32, den: sdenum as i32 };
+        (*self.context).gop_size     = self.effective_gop_size() as i32;
+        (*self.context).max_b_frames = self.max_b_frames as i32;
+        (*self.context).pix_fmt      = try!(self.negotiate_pix_fmt(codec));
+
+        self.clamp_gop_structure(codec);
+
+        if is_yuv((*self.context).pix_fmt) {
+            self.tag_colors();
+        }
+
+        if let Some(threads) = self.encoder_threads {
+            static FF_THREAD_FRAME: i32 = 1; // XXX: this should be defined by the bindings.
+            static FF_THREAD_SLICE: i32 = 2; // XXX: this should be defined by the bindings.
+
+            (*self.context).thread_count = threads as i32;
+            (*self.context).thread_type  = FF_THREAD_FRAME | FF_THREAD_SLICE;
+        }
+
+        if (*self.context).pix_fmt == avutil::PIX_FMT_YUVA420P {
+            // Tells the Matroska/WebM muxer to flag the stream as carrying an alpha channel.
+            let key   = CString::new(&b"alpha_mode"[..]).unwrap();
+            let value = CString::new(&b"1"[..]).unwrap();
+
+            let _ = avutil::av_dict_set(&mut (*self.video_st).metadata, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        if self.stereo {
+            // Tells the Matroska/WebM muxer to flag the stream as side-by-side 3D.
+            let key   = CString::new(&b"stereo_mode"[..]).unwrap();
+            let value = CString::new(&b"left_right"[..]).unwrap();
+
+            let _ = avutil::av_dict_set(&mut (*self.video_st).metadata, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        let hardware = is_hardware_encoder(codec);
+
+        if (*self.context).codec_id == avcodec::AV_CODEC_ID_MPEG1VIDEO && !hardware {
+            // Needed to avoid using macroblocks in which some coeffs overflow.
+            // This does not happen with normal video, it just happens here as
+            // the motion of the chroma plane does not match the luma plane.
+            (*self.context).mb_decision = 2;
+        }
+
+        // Some formats (e.g. MP4, MOV) want the stream headers (e.g. H.264 SPS/PPS) in the
+        // container rather than in the stream.
+        static AVFMT_GLOBALHEADER:       i32 = 0x0040;     // XXX: this should be defined by the bindings.
+        static CODEC_FLAG_GLOBAL_HEADER: i32 = 0x00400000; // XXX: this should be defined by the bindings.
+        if (*fmt).flags & AVFMT_GLOBALHEADER != 0 {
+            (*self.context).flags = (*self.context).flags | CODEC_FLAG_GLOBAL_HEADER;
+        }
+
+        // Per-frame PSNR, reported to the progress callback.
+        if self.quality_stats {
+            (*self.context).flags = (*self.context).flags | CODEC_FLAG_PSNR;
+        }
+
+        // Two-pass encoding. The statistics outlive the codec context, which does not own them.
+        (*self.context).flags = (*self.context).flags | self.pass_flags;
+
+        if let Some(ref stats) = self.stats_in {
+            (*self.context).stats_in = stats.as_ptr() as *mut libc::c_char;
+        }
+
+        // Open the codec.
+        let mut options: *mut AVDictionary = ptr::null_mut();
+
+        for &(ref key, ref value) in self.codec_options.iter() {
+            let _ = avutil::av_dict_set(&mut options, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        let ret = avcodec::avcodec_open2(self.context, codec, &mut options);
+
+        // The encoder removes the options it consumed; whatever is left was not recognized.
+        static AV_DICT_IGNORE_SUFFIX: i32 = 2; // XXX: this should be defined by the bindings.
+        let any = CString::new(&b""[..]).unwrap();
+        let mut entry = ptr::null_mut();
+
+        self.unused_options.clear();
+
+        loop {
+            entry = avutil::av_dict_get(options, any.as_ptr(), entry, AV_DICT_IGNORE_SUFFIX);
+
+            if entry.is_null() {
+                break;
+            }
+
+            let key = CStr::from_ptr((*entry).key).to_bytes();
+            self.unused_options.push(String::from_utf8_lossy(key).into_owned());
+        }
+
+        avutil::av_dict_free(&mut options);
+
+        if ret < 0 {
+            if hardware {
+                // Most likely, no suitable GPU or driver is available.
+                let name = CStr::from_ptr((*codec).name).to_bytes();
+
+                return Err(RecorderError::HardwareEncoderUnavailable(
+                    String::from_utf8_lossy(name).into_owned(), ret));
+            }
+
+            return Err(RecorderError::CodecOpen(ret));
+        }
+
+        /*
+         * Init the destination video frame.
+         */
+        self.frame = avutil::av_frame_alloc();
+
+        if self.frame.is_null() {
+            return Err(RecorderError::FrameAllocation);
+        }
+
+        (*self.frame).format = (*self.context).pix_fmt;
+        (*self.frame).width  = (*self.context).width;
+        (*self.frame).height = (*self.context).height;
+        (*self.frame).pts    = 0;
+        // The quantizer of constant quantizer mode is taken from each frame.
+        (*self.frame).quality = (*self.context).global_quality;
+
+        if self.stereo {
+            try!(stereo::tag_side_by_side(self.frame));
+        }
+
+        // alloc the buffer
+        let nframe_bytes = avcodec::avpicture_get_size((*self.context).pix_fmt,
+                                                       self.width as i32,
+                                                       self.height as i32);
+        
+        let reps = std::iter::repeat(0u8).take(nframe_bytes as usize);
+        self.frame_buf = Vec::<u8>::from_iter(reps);
+        //self.frame_buf = Vec::from_elem(nframe_bytes as usize, 0u8);
+
+        // Room for a padded RGB24 snapshot of the size of the video, so that the snapshots do
+        // not grow the buffer. Captures of a different size, e.g. after the window is
+        // resized, reallocate it once.
+        let stride     = round_up_to_multiple(self.width * 3, INPUT_ROW_ALIGN);
+        let tmp_needed = stride * (self.height + 1);
+        let tmp_len    = self.tmp_frame_buf.len();
+
+        self.tmp_frame_buf.reserve(tmp_needed.saturating_sub(tmp_len));
+
+        let _ = avcodec::avpicture_fill(self.frame as *mut avcodec::AVPicture,
+                                        self.frame_buf.get(0).unwrap(),
+                                        (*self.context).pix_fmt,
+                                        self.width as i32,
+                                        self.height as i32);
+
+        /*
+         * Init the temporary video frame.
+         */
+        self.tmp_frame = avutil::av_frame_alloc();
+
+        if self.tmp_frame.is_null() {
+            return Err(RecorderError::FrameAllocation);
+        }
+
+        // The temporary frame holds the captured RGB image, not the encoder format. Snapshots
+        // from other sources, e.g. `snap_rgba`, update the format along with the rest (width,
+        // height, data, linesize), which is set at the moment of the snapshot.
+        (*self.tmp_frame).format = avutil::PIX_FMT_RGB24;
+
+        // Open the output file.
+        match self.custom_output {
+            Some(ref mut output) => {
+                let pb = memory::alloc_avio_context(&mut **output);
+
+                if pb.is_null() {
+                    return Err(RecorderError::AvioAllocation);
+                }
+
+                static AVFMT_FLAG_CUSTOM_IO: i32 = 0x0080; // XXX: this should be defined by the bindings.
+                (*self.format_context).pb    = pb;
+                (*self.format_context).flags = (*self.format_context).flags | AVFMT_FLAG_CUSTOM_IO;
+            },
+            None => {
+                static AVIO_FLAG_WRITE: i32 = 2; // XXX: this should be defined by the bindings.
+                let ret = avformat::avio_open(&mut (*self.format_context).pb, path_str.as_ptr(), AVIO_FLAG_WRITE);
+
+                if ret < 0 {
+                    return Err(RecorderError::FileOpen(self.path.clone(), ret));
+                }
+            }
+        }
+
+        for &(ref key, ref value) in self.metadata.iter() {
+            let _ = avutil::av_dict_set(&mut (*self.format_context).metadata, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        // Non-seekable outputs, like FIFOs, pipes and writers, do not allow rewriting the index
+        // of MP4 and MOV files once the video is complete, so they are fragmented instead.
+        // Periodic syncs need fragments too, for the synced part to be readable on its own.
+        let mut muxer_options: *mut AVDictionary = ptr::null_mut();
+        let fragmented = (*(*self.format_context).pb).seekable == 0 || self.sync_every.is_some();
+
+        if fragmented && is_mov_muxer((*self.format_context).oformat) {
+            let key   = CString::new("movflags").unwrap();
+            let value = CString::new("frag_keyframe+empty_moov").unwrap();
+            let _     = avutil::av_dict_set(&mut muxer_options, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        let ret = avformat::avformat_write_header(self.format_context, &mut muxer_options);
+
+        avutil::av_dict_free(&mut muxer_options);
+
+        if ret < 0 {
+            return Err(RecorderError::HeaderWriting(ret));
+        }
+
+        Ok(())
+    }
+
+    // Closes the output file or frees the custom AVIO context, if open. Returns the error code of
+    // the output, if any.
+    unsafe fn close_output(&mut self) -> i32 {
+        if self.format_context.is_null() || (*self.format_context).pb.is_null() {
+            return 0;
+        }
+
+        let ret = if self.custom_output.is_some() {
+            memory::free_avio_context((*self.format_context).pb)
+        }
+        else {
+            avformat::avio_close((*self.format_context).pb)
+        };
+
+        (*self.format_context).pb = ptr::null_mut();
+
+        ret
+    }
+
+    /// Checks the settings without writing anything.
+    ///
+    /// This looks up the container format and the encoder, checks that they support video and
+    /// the requested pixel format, and, unless the recorder writes into memory or a writer, that
+    /// a file can be created in the directory of the output path. Every context allocated on the
+    /// way is freed, and the probe file removed. Use this to fail fast before a long recording;
+    /// the errors are those `init` would return.
+    pub fn validate(&self) -> Result<(), RecorderError> {
+        let path_str = match os_to_cstring(self.path.as_os_str()) {
+            Some(s) => s,
+            None    => return Err(RecorderError::InvalidPath(self.path.clone()))
+        };
+
+        unsafe {
+            let format_context = try!(self.alloc_output_context(&path_str));
+
+            let checked = self.check_encoder((*format_context).oformat);
+
+            avformat::avformat_free_context(format_context);
+
+            try!(checked);
+        }
+
+        if self.custom_output.is_none() && stream_format(&self.path).is_none() {
+            // Probe with a scratch file, so that an existing output is left untouched.
+            let mut probe_name = OsStr::new(".").to_os_string();
+            probe_name.push(self.path.file_name().unwrap_or(OsStr::new("video")));
+            probe_name.push(".probe");
+
+            let probe_path = self.path.with_file_name(&probe_name);
+
+            let _ = try!(fs::OpenOptions::new().write(true).create(true).open(&probe_path));
+            try!(fs::remove_file(&probe_path));
+        }
+
+        Ok(())
+    }
+
+    // Checks that the output format supports video, and that its encoder supports the pixel
+    // format.
+    unsafe fn check_encoder(&self, fmt: *mut AVOutputFormat) -> Result<(), RecorderError> {
+        if (*fmt).video_codec == avcodec::AV_CODEC_ID_NONE {
+            return Err(RecorderError::NoVideoSupport);
+        }
+
+        let codec = try!(self.find_encoder(fmt));
+
+        try!(check_codec_container(fmt, codec));
+
+        let _ = try!(self.negotiate_pix_fmt(codec));
+
+        Ok(())
+    }
+
+    // Creates the output context, with the requested container format or, failing that, the one
+    // guessed from the path, or else the fallback format.
+    unsafe fn alloc_output_context(&self, path_str: &CString) -> Result<*mut AVFormatContext, RecorderError> {
+        let mut fmt = ptr::null_mut();
+
+        match self.format_name {
+            Some(ref name) => {
+                // the container type is known, no need to guess.
+                if let Ok(name) = CString::new(name.as_bytes()) {
+                    let _ = avformat::avformat_alloc_output_context2(&mut fmt, ptr::null_mut(), name.as_ptr(), path_str.as_ptr());
+                }
+            },
+            None if stream_format(&self.path).is_some() => {
+                // network protocols have no file extension to guess from.
+                let name = CString::new(stream_format(&self.path).unwrap()).unwrap();
+
+                let _ = avformat::avformat_alloc_output_context2(&mut fmt, ptr::null_mut(), name.as_ptr(), path_str.as_ptr());
+            },
+            None => {
+                // try to guess the container type from the path.
+                let _ = avformat::avformat_alloc_output_context2(&mut fmt, ptr::null_mut(), ptr::null(), path_str.as_ptr());
+
+                if fmt.is_null() {
+                    // could not guess, use the fallback format, MPEG by default.
+                    let fallback = match self.fallback_format {
+                        Some(ref name) => name,
+                        None           => return Err(RecorderError::UnknownFormat(self.path.clone()))
+                    };
+
+                    if let Ok(name) = CString::new(fallback.as_bytes()) {
+                        let _ = avformat::avformat_alloc_output_context2(&mut fmt, ptr::null_mut(), name.as_ptr(), path_str.as_ptr());
+                    }
+                }
+            }
+        }
+
+        if fmt.is_null() {
+            return Err(RecorderError::OutputContextCreation);
+        }
+
+        Ok(fmt)
+    }
+
+    // Looks up the encoder: either the one named by the user, or the container's default.
+    unsafe fn find_encoder(&self, fmt: *mut AVOutputFormat) -> Result<*mut AVCodec, RecorderError> {
+        match self.codec_name {
+            Some(ref name) => {
+                let codec = match CString::new(name.as_bytes()) {
+                    Ok(cname) => avcodec::avcodec_find_encoder_by_name(cname.as_ptr()),
+                    Err(_)    => ptr::null_mut()
+                };
+
+                if codec.is_null() {
+                    Err(RecorderError::EncoderNotFound(name.clone()))
+                }
+                else {
+                    Ok(codec)
+                }
+            },
+            None => {
+                let codec = avcodec::avcodec_find_encoder((*fmt).video_codec);
+
+                if codec.is_null() {
+                    Err(RecorderError::CodecNotFound((*fmt).video_codec as i32))
+                }
+                else {
+                    Ok(codec)
+                }
+            }
+        }
+    }
+
+    // Adapts the GOP size and B-frame count set on the codec context to what the encoder supports.
+    unsafe fn clamp_gop_structure(&mut self, codec: *mut AVCodec) {
+        static AV_CODEC_PROP_INTRA_ONLY: i32 = 1 << 0; // XXX: this should be defined by the bindings.
+        static CODEC_CAP_DELAY:          i32 = 0x0020; // XXX: this should be defined by the bindings.
+        static FF_MAX_B_FRAMES:          i32 = 16;     // XXX: this should be defined by the bindings.
+
+        let descriptor = avcodec::avcodec_descriptor_get((*codec).id);
+        let intra_only = !descriptor.is_null() && (*descriptor).props & AV_CODEC_PROP_INTRA_ONLY != 0;
+
+        if intra_only {
+            // Every frame is a keyframe anyway.
+            if (*self.context).gop_size != 0 || (*self.context).max_b_frames != 0 {
+                warn(&format!("the encoder is intra-only: using a GOP size and B-frame count of 0 \
+                               instead of {} and {}.",
+                              (*self.context).gop_size, (*self.context).max_b_frames));
+            }
+
+            (*self.context).gop_size     = 0;
+            (*self.context).max_b_frames = 0;
+            return;
+        }
+
+        // Encoders that cannot delay their output cannot reorder frames.
+        let max_b_frames = if (*codec).capabilities & CODEC_CAP_DELAY == 0 { 0 } else { FF_MAX_B_FRAMES };
+
+        if (*self.context).max_b_frames > max_b_frames {
+            warn(&format!("the encoder supports at most {} B-frames: clamping max_b_frames from {}.",
+                          max_b_frames, (*self.context).max_b_frames));
+
+            (*self.context).max_b_frames = max_b_frames;
+        }
+
+        // A GOP must hold at least one frame past the B-frames. A GOP size of 0 means intra-only.
+        if (*self.context).gop_size != 0 && (*self.context).gop_size <= (*self.context).max_b_frames {
+            let gop_size = (*self.context).max_b_frames + 1;
+
+            warn(&format!("a GOP size of {} cannot hold {} B-frames: using a GOP size of {}.",
+                          (*self.context).gop_size, (*self.context).max_b_frames, gop_size));
+
+            (*self.context).gop_size = gop_size;
+        }
+    }
+
+    // Sets the color range, matrix, primaries and transfer characteristics of the stream. Unless
+    // set explicitly, HD videos use BT.709 and SD ones BT.601, in limited range except for the
+    // full-range JPEG formats.
+    unsafe fn tag_colors(&mut self) {
+        let jpeg_fmts = [avutil::PIX_FMT_YUVJ420P, avutil::PIX_FMT_YUVJ422P, avutil::PIX_FMT_YUVJ444P];
+
+        let default_range = if jpeg_fmts.contains(&(*self.context).pix_fmt) {
+            avcodec::AVCOL_RANGE_JPEG
+        }
+        else {
+            avcodec::AVCOL_RANGE_MPEG
+        };
+
+        let (default_space, default_primaries) = if self.height >= 720 {
+            (avcodec::AVCOL_SPC_BT709, avcodec::AVCOL_PRI_BT709)
+        }
+        else {
+            (avcodec::AVCOL_SPC_SMPTE170M, avcodec::AVCOL_PRI_SMPTE170M)
+        };
+
+        let primaries = self.color_primaries.unwrap_or(default_primaries);
+
+        let trc = match primaries {
+            p if p == avcodec::AVCOL_PRI_BT709     => avcodec::AVCOL_TRC_BT709,
+            p if p == avcodec::AVCOL_PRI_SMPTE170M => avcodec::AVCOL_TRC_SMPTE170M,
+            _                                      => avcodec::AVCOL_TRC_UNSPECIFIED
+        };
+
+        (*self.context).color_range     = self.color_range.unwrap_or(default_range);
+        (*self.context).colorspace      = self.colorspace.unwrap_or(default_space);
+        (*self.context).color_primaries = primaries;
+        (*self.context).color_trc       = trc;
+    }
+
+    // Picks the pixel format to encode with: the requested one if the encoder supports it, or a
+    // format the encoder supports, preferring YUV420P.
+    unsafe fn negotiate_pix_fmt(&self, codec: *mut AVCodec) -> Result<i32, RecorderError> {
+        let supported = supported_pix_fmts(codec);
+
+        match self.pix_fmt {
+            Some(pix_fmt) => {
+                if supported.is_empty() || supported.contains(&pix_fmt) {
+                    Ok(pix_fmt)
+                }
+                else {
+                    Err(RecorderError::UnsupportedPixelFormat(pix_fmt, supported))
+                }
+            },
+            None => {
+                // JPEG uses full-range YUV: MJPEG rejects limited-range formats unless asked to
+                // be non-standard.
+                let preferred = if (*codec).id == avcodec::AV_CODEC_ID_MJPEG {
+                    avutil::PIX_FMT_YUVJ420P
+                }
+                else {
+                    avutil::PIX_FMT_YUV420P
+                };
+
+                if supported.is_empty() || supported.contains(&preferred) {
+                    Ok(preferred)
+                }
+                else {
+                    Ok(supported[0])
+                }
+            }
+        }
+    }
+
+    // Initializes the recorder, first adopting the size of the input image if the video size was
+    // left unspecified.
+    fn init_with_input_size(&mut self, width: usize, height: usize) -> Result<(), RecorderError> {
+        if !self.initialized && (self.width == 0 || self.height == 0) {
+            self.width  = round_up_to_even(width);
+            self.height = round_up_to_even(height);
+        }
+
+        self.init()
+    }
+
+    /// Finishes the recording.
+    ///
+    /// This flushes the delayed frames out of the encoder, writes the container trailer and
+    /// closes the output file. Unlike simply dropping the recorder, this reports any error that
+    /// occurred, so you know for sure whether the output file is complete. This includes a packet
+    /// that failed to be written during the recording, even if the error returned by that
+    /// snapshot was ignored: the file then misses a frame. Dropping the recorder only logs the
+    /// errors.
+    pub fn finalize(mut self) -> Result<(), RecorderError> {
+        self.finish()
+    }
+
+    /// Finishes the recording, then moves the output file to `final_path`.
+    ///
+    /// The file is moved only if finalization succeeded, so that nothing watching `final_path`
+    /// ever sees an incomplete video. When `final_path` is on another file system, the file is
+    /// first copied next to `final_path` and then renamed, so the move stays atomic.
+    ///
+    /// Fails with `RecorderError::NoOutputFile` if the recorder writes into memory or a writer;
+    /// the recording is then finished as on drop.
+    pub fn finalize_to<P: ?Sized + AsRef<OsStr>>(mut self, final_path: &P) -> Result<(), RecorderError> {
+        if self.custom_output.is_some() {
+            return Err(RecorderError::NoOutputFile);
+        }
+
+        try!(self.finish());
+
+        let final_path = Path::new(final_path);
+
+        if fs::rename(&self.path, final_path).is_err() {
+            // Renaming fails across file systems. Copy to the destination file system first.
+            let mut part_name = final_path.file_name().unwrap_or(OsStr::new("video")).to_os_string();
+            part_name.push(".part");
+
+            let part_path = final_path.with_file_name(&part_name);
+
+            let _ = try!(fs::copy(&self.path, &part_path));
+            try!(fs::rename(&part_path, final_path));
+            try!(fs::remove_file(&self.path));
+        }
+
+        Ok(())
+    }
+
+    // Finalizes the recording without consuming the recorder.
+    fn finish(&mut self) -> Result<(), RecorderError> {
+        // Finishing twice, e.g. after `set_finalize_at_limit`, must not free the contexts again.
+        let finished   = self.finalized;
+        self.finalized = true;
+
+        if finished || !self.initialized {
+            return Ok(());
+        }
+
+        let flushed = self.flush_delayed_frames();
+        let closed  = self.write_trailer();
+
+        self.free_contexts();
+
+        try!(flushed);
+        try!(closed);
+
+        // A packet lost earlier leaves the output incomplete, even if the end went well.
+        match self.write_error.take() {
+            Some((code, msg)) => Err(RecorderError::FrameWriting(code, msg)),
+            None              => Ok(())
+        }
+    }
+
+    /// Sets the number of threads the encoder may use, 0 meaning one per CPU core.
+    ///
+    /// Encoders such as libx264 or libvpx then encode several frames, or several slices of each
+    /// frame, in parallel, which greatly improves the throughput of realtime captures. Frame
+    /// threading delays the output by a few frames, which only matters for live streams.
+    /// Encoders without threading support ignore this. Setting the count after initialization
+    /// fails with `RecorderError::AlreadyInitialized`.
+    pub fn set_encoder_threads(&mut self, threads: usize) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        self.encoder_threads = Some(threads);
+
+        Ok(())
+    }
+
+    /// Selects the container format by name, e.g. "mp4", "matroska" or "flv", instead of
+    /// guessing it from the output path.
+    ///
+    /// This is required for destinations without a meaningful extension, e.g. "pipe:1" to write
+    /// to the standard output. Since "mp4" needs a seekable output, prefer "matroska" or
+    /// "mpegts" for pipes. An unknown name makes the initialization fail with
+    /// `RecorderError::OutputContextCreation`. Setting the format after initialization fails with
+    /// `RecorderError::AlreadyInitialized`.
+    pub fn force_format(&mut self, name: &str) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        self.format_name = Some(name.to_string());
+
+        Ok(())
+    }
+
+    /// Splits the recording into consecutive files of `secs` seconds each.
+    ///
+    /// The files are named after the output path with a numeric suffix: recording into
+    /// "clip.mp4" produces "clip_000.mp4", "clip_001.mp4", etc. When a snapshot would cross a
+    /// segment boundary, the current file is finalized as with `reset`, and the snapshot starts
+    /// the next one, on a keyframe. The timestamps given to `snap_at` are relative to the start
+    /// of the current segment and do not trigger a split.
+    ///
+    /// Fails with `RecorderError::AlreadyInitialized` once the recording has started, with
+    /// `RecorderError::NoOutputFile` if the recorder writes into memory or a writer, and with
+    /// `RecorderError::InvalidArgument` if `secs` is not positive.
+    pub fn set_segment_duration(&mut self, secs: f64) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        if self.custom_output.is_some() {
+            return Err(RecorderError::NoOutputFile);
+        }
+
+        if !(secs > 0.0) {
+            return Err(RecorderError::InvalidArgument(
+                format!("the segment duration must be positive, got {}s", secs)));
+        }
+
+        if self.segment_duration.is_none() {
+            self.segment_base = self.path.clone();
+            self.path         = segment_path(&self.segment_base, self.segment_index);
+        }
+
+        self.segment_duration = Some(secs);
+
+        Ok(())
+    }
+
+    /// The index of the segment being recorded, starting at 0. Always 0 without segmenting.
+    pub fn segment_index(&self) -> usize {
+        self.segment_index
+    }
+
+    // Starts the next segment if the next frame would cross the segment boundary.
+    fn split_segment_if_due(&mut self) -> Result<(), RecorderError> {
+        let duration = match self.segment_duration {
+            Some(duration) if self.initialized => duration,
+            _                                  => return Ok(())
+        };
+
+        let next_pts_secs = unsafe { self.next_pts_secs() };
+
+        if next_pts_secs < duration {
+            return Ok(());
+        }
+
+        self.segment_index = self.segment_index + 1;
+
+        // The next segment continues the same recording, unlike after `reset`.
+        let frames = self.segment_frames + self.curr_frame_index;
+        let path   = segment_path(&self.segment_base, self.segment_index);
+        let reset  = self.reset(&path);
+
+        self.segment_frames = frames;
+
+        reset
+    }
+
+    /// Forces the frames buffered by the encoder out to the output, without ending the
+    /// recording, e.g. so that a live monitor sees the latest frames.
+    ///
+    /// Encoders cannot take new frames once drained, so the encoder is then restarted with the
+    /// same settings, and the next snapshot is encoded as a keyframe. Recording simply goes on
+    /// with `snap`; no reinitialization is needed. The extra keyframes cost bit rate, so do not
+    /// flush after every frame. The restarted encoder does not use B-frames, whose reordering
+    /// would make its timestamps overlap the ones already written, which slightly increases the
+    /// bit rate for the rest of the recording. Does nothing if the recorder is not initialized.
+    pub fn flush(&mut self) -> Result<(), RecorderError> {
+        try!(self.check_not_finalized());
+
+        if !self.initialized {
+            return Ok(());
+        }
+
+        try!(self.flush_delayed_frames());
+        try!(self.sync_output());
+
+        unsafe {
+            try!(self.reopen_codec());
+        }
+
+        self.force_keyframe = true;
+
+        Ok(())
+    }
+
+    // Pushes the packets written so far to the output: flushes the interleaving queue of the
+    // muxer, ends the current fragment of fragmented formats, then flushes the I/O buffer.
+    fn sync_output(&mut self) -> Result<(), RecorderError> {
+        static AVFMT_ALLOW_FLUSH: i32 = 0x10000; // XXX: this should be defined by the bindings.
+
+        unsafe {
+            let ret = avformat::av_interleaved_write_frame(self.format_context, ptr::null_mut());
+
+            if ret < 0 {
+                return Err(RecorderError::FrameWriting(ret, ffmpeg_error_string(ret)));
+            }
+
+            if (*(*self.format_context).oformat).flags & AVFMT_ALLOW_FLUSH != 0 {
+                let ret = avformat::av_write_frame(self.format_context, ptr::null_mut());
+
+                if ret < 0 {
+                    return Err(RecorderError::FrameWriting(ret, ffmpeg_error_string(ret)));
+                }
+            }
+
+            avformat::avio_flush((*self.format_context).pb);
+        }
+
+        Ok(())
+    }
+
+    // Closes the drained encoder and opens it again with the same settings.
+    unsafe fn reopen_codec(&mut self) -> Result<(), RecorderError> {
+        let codec = (*self.context).codec as *mut AVCodec;
+
+        let _ = avcodec::avcodec_close(self.context);
+
+        // A restarted encoder reorders frames from scratch: with B-frames, its first decoding
+        // timestamps would fall before those already written, which the muxers reject.
+        (*self.context).max_b_frames = 0;
+
+        // The private options of the encoder were freed along with it.
+        let mut options: *mut AVDictionary = ptr::null_mut();
+
+        if let Some(crf) = self.crf {
+            let name  = CString::new(&b"crf"[..]).unwrap();
+            let value = CString::new(crf.to_string()).unwrap();
+
+            let _ = avutil::av_dict_set(&mut options, name.as_ptr(), value.as_ptr(), 0);
+        }
+
+        for &(ref key, ref value) in self.codec_options.iter() {
+            let _ = avutil::av_dict_set(&mut options, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        let ret = avcodec::avcodec_open2(self.context, codec, &mut options);
+
+        avutil::av_dict_free(&mut options);
+
+        if ret < 0 {
+            return Err(RecorderError::CodecOpen(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Finishes the recording, then starts a new one into `new_path` with the same settings.
+    ///
+    /// This finalizes the current file as `finalize` does, and reports its errors; the recorder is
+    /// reset in any case. The encoder settings, codec options, metadata, capture region and video
+    /// size are kept (a size derived from the first snapshot included), while the frame count,
+    /// the timestamps and the progress counters start over. The new recording is initialized
+    /// lazily by the next snapshot, as usual.
+    ///
+    /// Fails with `RecorderError::NoOutputFile`, without stopping the recording, if the recorder
+    /// writes into memory or a writer.
+    pub fn reset<P: ?Sized + AsRef<OsStr>>(&mut self, new_path: &P) -> Result<(), RecorderError> {
+        if self.custom_output.is_some() {
+            return Err(RecorderError::NoOutputFile);
+        }
+
+        let stopped = self.stop();
+
+        self.path = PathBuf::from(new_path);
+
+        stopped
+    }
+
+    /// Finishes the recording, and returns the recorder to its state before initialization.
+    ///
+    /// This is `finalize` for recorders that cannot be moved, e.g. fields of a bigger structure:
+    /// the output file is finalized and closed, the encoder and scaling contexts are freed, and
+    /// the errors are reported as by `finalize`. The recorder is stopped in any case. The
+    /// settings persist across stop and restart, as for `reset`, while the frame count and the
+    /// timestamps start over. The next snapshot starts a new recording: call `set_path` before,
+    /// or it overwrites this one.
+    ///
+    /// Fails with `RecorderError::NoOutputFile`, without stopping the recording, if the recorder
+    /// writes into memory or a writer: use `finalize` instead.
+    pub fn stop(&mut self) -> Result<(), RecorderError> {
+        if self.custom_output.is_some() {
+            return Err(RecorderError::NoOutputFile);
+        }
+
+        let finished = self.finish();
+
+        self.initialized      = false;
+        self.finalized        = false;
+        self.curr_frame_index = 0;
+        self.segment_frames   = 0;
+        self.bytes_written    = 0;
+        self.packets_written  = 0;
+        self.write_error      = None;
+        self.realtime_start   = None;
+        self.force_keyframe   = false;
+        self.irregular_pts    = false;
+        self.unused_options.clear();
+
+        finished
+    }
+
+    /// Changes the output file of the next recording, e.g. after `stop`.
+    ///
+    /// This fails with `RecorderError::AlreadyInitialized` while recording, and with
+    /// `RecorderError::NoOutputFile` if the recorder writes into memory or a writer.
+    pub fn set_path<P: ?Sized + AsRef<OsStr>>(&mut self, path: &P) -> Result<(), RecorderError> {
+        try!(self.check_not_initialized());
+
+        if self.custom_output.is_some() {
+            return Err(RecorderError::NoOutputFile);
+        }
+
+        self.path = PathBuf::from(path);
+
+        Ok(())
+    }
+
+    // Gets the delayed frames out of the encoder and writes them to the output.
+    //
+    // A packet that fails to be written does not stop the drain, so that as many frames as
+    // possible reach the output; the first failure is returned at the end.
+    fn flush_delayed_frames(&mut self) -> Result<(), RecorderError> {
+        let mut written    = Ok(());
+        let mut got_output = 1;
+        while got_output != 0 {
+            let ret;
+            let pkt: *mut AVPacket = &mut self.packet;
+
+            unsafe {
+                ret = avcodec::avcodec_encode_video2(self.context, pkt, ptr::null(), &mut got_output);
+            }
+
+            if ret < 0 {
+                return Err(RecorderError::FrameEncoding(ret, ffmpeg_error_string(ret)));
+            }
+
+            if got_output != 0 {
+                self.collect_stats();
+
+                if let Err(e) = self.write_packet() {
+                    if written.is_ok() {
+                        written = Err(e);
+                    }
+                }
+            }
+        }
+
+        written
+    }
+
+    // Accumulates the rate control statistics output by the encoder during a first pass.
+    //
+    // The statistics describe the packet just output: calls without output would repeat them.
+    fn collect_stats(&mut self) {
+        static CODEC_FLAG_PASS1: i32 = 0x0200; // XXX: this should be defined by the bindings.
+
+        if self.pass_flags & CODEC_FLAG_PASS1 == 0 {
+            return;
+        }
+
+        unsafe {
+            let stats = (*self.context).stats_out;
+
+            if !stats.is_null() {
+                self.stats_out.push_str(&String::from_utf8_lossy(CStr::from_ptr(stats).to_bytes()));
+            }
+        }
+    }
+
+    // Writes the container trailer and closes the output file.
+    fn write_trailer(&mut self) -> Result<(), RecorderError> {
+        let trailer_ret = unsafe { avformat::av_write_trailer(self.format_context) };
+
+        // The output is closed even if the trailer failed, so that the file is not left open.
+        let close_ret = unsafe { self.close_output() };
+
+        if trailer_ret < 0 {
+            return Err(RecorderError::TrailerWriting(trailer_ret));
+        }
+
+        if close_ret < 0 {
+            return Err(RecorderError::FileClose(close_ret));
+        }
+
+        if let Some(ref mut output) = self.custom_output {
+            try!(output.flush());
+        }
+
+        Ok(())
+    }
+
+    // Free things and stuffs.
+    fn free_contexts(&mut self) {
+        unsafe {
+            // The scaler only reads and writes the frames while converting, so it can go first.
+            if !self.scale_context.is_null() {
+                swscale::sws_freeContext(self.scale_context);
+            }
+
+            // The codec context belongs to the video stream: it is only closed here, and freed
+            // along with the stream.
+            if !self.context.is_null() {
+                let _ = avcodec::avcodec_close(self.context);
+            }
+
+            // avutil::av_freep((*self.frame).data[0] as *mut c_void);
+            // Null frames, e.g. after a failed initialization, are left alone.
+            avutil::av_frame_free(&mut self.frame);
+            avutil::av_frame_free(&mut self.tmp_frame);
+            avcodec::av_free_packet(&mut self.packet);
+
+            // This also frees the video stream, and its codec context.
+            if !self.format_context.is_null() {
+                avformat::avformat_free_context(self.format_context);
+            }
+        }
+
+        self.scale_context  = ptr::null_mut();
+        self.context        = ptr::null_mut();
+        self.format_context = ptr::null_mut();
+        self.video_st       = ptr::null_mut();
+    }
+}
+
+// The raw pointers make `Recorder` `!Send` by default. Moving it to another thread is sound:
+//
+// * the recorder exclusively owns every FFmpeg context it points to (format, codec and scaling
+//   contexts, frames, stream, and the AVIO context of in-memory outputs); none of these pointers
+//   is ever handed out, so nothing else can alias them;
+// * FFmpeg does not tie these contexts to the thread that created them, it only requires that
+//   they are not used concurrently, which `&mut self` already guarantees;
+// * the pixel buffers referenced by the frames are owned by the recorder and move along with it.
+//
+// `Recorder` is deliberately not `Sync`.
+unsafe impl Send for Recorder { }
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.initialized && !self.finalized {
+            // Without the trailer, containers like MP4 lack their index and are unplayable.
+            // Errors cannot be returned from here, so they are only logged; use `finalize` to
+            // catch them.
+            if let Err(e) = self.finish() {
+                log::log(Level::Error, &format!("the recording of {} is incomplete: {}",
+                                                self.path.display(), e));
+            }
+        }
+    }
+}
+
+// Shows the configuration and state, with the FFmpeg contexts as allocated or not.
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Recorder {{ path: {:?}, width: {}, height: {}, bit_rate: {}, crf: {:?}, time_base: {:?}, \
+                   gop_size: {}, max_b_frames: {}, pix_fmt: {:?}, codec_name: {:?}, initialized: {}, \
+                   finalized: {}, paused: {}, curr_frame_index: {}, context: {}, format_context: {}, \
+                   video_st: {}, scale_context: {} }}",
+               self.path, self.width, self.height, self.bit_rate, self.crf, self.time_base,
+               self.gop_size, self.max_b_frames, self.pix_fmt, self.codec_name, self.initialized,
+               self.finalized, self.paused, self.curr_frame_index, !self.context.is_null(),
+               !self.format_context.is_null(), !self.video_st.is_null(), !self.scale_context.is_null())
+    }
+}
+
+/// Records a video from images already in memory, with the default settings.
+///
+/// Each frame is tightly packed RGB24 data with its width and height, as given to
+/// `Recorder::snap_rgb`. The video is finalized once every frame is encoded; the first error
+/// stops the recording.
+///
+/// # Arguments:
+/// * `path`   - path to the output file.
+/// * `width`  - width of the recorded video. If zero, the size of the first frame is used.
+/// * `height` - height of the recorded video. If zero, the size of the first frame is used.
+/// * `frames` - the images to record, in order.
+pub fn record_frames<'a, P, I>(path: &P, width: usize, height: usize, frames: I) -> Result<(), RecorderError>
+    where P: ?Sized + AsRef<OsStr>,
+          I: IntoIterator<Item = (&'a [u8], usize, usize)> {
+    RecorderBuilder::new().record_frames(path, width, height, frames)
+}
+
+impl RecorderBuilder {
+    /// Records a video from images already in memory.
+    ///
+    /// See `record_frames` for the meaning of the arguments.
+    pub fn record_frames<'a, P, I>(&self, path: &P, width: usize, height: usize, frames: I)
+                                   -> Result<(), RecorderError>
+        where P: ?Sized + AsRef<OsStr>,
+              I: IntoIterator<Item = (&'a [u8], usize, usize)> {
+        let mut recorder = self.build(path, width, height);
+
+        for (data, w, h) in frames {
+            let _ = try!(recorder.snap_rgb(data, w, h));
+        }
+
+        recorder.finalize()
+    }
+}
+
+// The pixel formats supported by an encoder. Empty if the encoder does not tell.
+unsafe fn supported_pix_fmts(codec: *const AVCodec) -> Vec<i32> {
+    let mut res     = Vec::new();
+    let mut pix_fmt = (*codec).pix_fmts;
+
+    if !pix_fmt.is_null() {
+        while *pix_fmt != avutil::PIX_FMT_NONE {
+            res.push(*pix_fmt);
+            pix_fmt = pix_fmt.offset(1);
+        }
+    }
+
+    res
+}
+
+// Whether a pixel format is YUV, as opposed to RGB, paletted or grayscale.
+fn is_yuv(pix_fmt: i32) -> bool {
+    static PIX_FMT_PAL: u8 = 1 << 1; // XXX: this should be defined by the bindings.
+    static PIX_FMT_RGB: u8 = 1 << 5; // XXX: this should be defined by the bindings.
+
+    unsafe {
+        let desc = avutil::av_pix_fmt_desc_get(pix_fmt);
+
+        !desc.is_null() && (*desc).nb_components >= 3 && (*desc).flags & (PIX_FMT_PAL | PIX_FMT_RGB) == 0
+    }
+}
+
+// Whether the encoder runs on dedicated hardware, e.g. "h264_nvenc".
+//
+// Such encoders accept system-memory frames, but fail to open without a suitable GPU or driver.
+unsafe fn is_hardware_encoder(codec: *const AVCodec) -> bool {
+    static HARDWARE_SUFFIXES: [&'static str; 6] = ["nvenc", "_qsv", "_vaapi", "_videotoolbox", "_amf", "_omx"];
+
+    let name = String::from_utf8_lossy(CStr::from_ptr((*codec).name).to_bytes()).into_owned();
+
+    HARDWARE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+// Captures the window into `buf`, and returns the size of the image.
+//
+// `Window::width` and `height` are in logical pixels, while `snap` reads the framebuffer, which
+// is larger on HiDPI displays. The actual size is thus derived from the length of the buffer,
+// assuming the framebuffer has the aspect ratio of the window. The buffer may also be shorter,
+// or empty, when the window has not rendered a frame yet.
+#[cfg(feature = "kiss3d")]
+fn snap_window(window: &Window, buf: &mut Vec<u8>) -> Result<(usize, usize), RecorderError> {
+    window.snap(buf);
+
+    let width    = window.width()  as usize;
+    let height   = window.height() as usize;
+    let expected = width * height * 3;
+    let pixels   = buf.len() / 3;
+
+    if width == 0 || height == 0 || buf.len() < expected {
+        return Err(RecorderError::IncompleteFrame(expected, buf.len()));
+    }
+
+    if buf.len() == expected {
+        return Ok((width, height));
+    }
+
+    let scale    = (pixels as f64 / (width * height) as f64).sqrt();
+    let fb_width = (width as f64 * scale).round() as usize;
+
+    // The scaled width may be off by one after rounding. A width of 0 cannot hold the pixels.
+    let candidates = [Some(fb_width), fb_width.checked_add(1), fb_width.checked_sub(1)];
+
+    for w in candidates.iter().filter_map(|w| *w).filter(|&w| w > 0) {
+        if buf.len() == w * (pixels / w) * 3 {
+            return Ok((w, pixels / w));
+        }
+    }
+
+    Err(RecorderError::IncompleteFrame(expected, buf.len()))
+}
+
+// Fails if the muxer knows it cannot hold the codec, rather than later when writing the header.
+//
+// Muxers without a list of supported codecs are given the benefit of the doubt.
+unsafe fn check_codec_container(fmt: *mut AVOutputFormat, codec: *mut AVCodec) -> Result<(), RecorderError> {
+    static FF_COMPLIANCE_NORMAL: i32 = 0; // XXX: this should be defined by the bindings.
+
+    if avformat::avformat_query_codec(fmt, (*codec).id, FF_COMPLIANCE_NORMAL) == 0 {
+        let codec_name  = String::from_utf8_lossy(CStr::from_ptr((*codec).name).to_bytes()).into_owned();
+        let format_name = String::from_utf8_lossy(CStr::from_ptr((*fmt).name).to_bytes()).into_owned();
+
+        return Err(RecorderError::IncompatibleCodecContainer(codec_name, format_name));
+    }
+
+    Ok(())
+}
+
+// Whether the muxer writes MP4 or one of the other MOV-based formats.
+unsafe fn is_mov_muxer(fmt: *const AVOutputFormat) -> bool {
+    static MOV_MUXERS: [&'static str; 8] = ["mov", "mp4", "ipod", "psp", "3gp", "3g2", "ismv", "f4v"];
+
+    let name = String::from_utf8_lossy(CStr::from_ptr((*fmt).name).to_bytes()).into_owned();
+
+    MOV_MUXERS.iter().any(|m| *m == name)
+}
+
+// Creates an empty packet. Its data will be allocated by the encoder.
+//
+// `av_init_packet` leaves `data` and `size` untouched, so the packet is zeroed first rather than
+// left uninitialized (`av_packet_alloc` is not available in libavcodec 55).
+fn new_packet() -> AVPacket {
+    let mut pkt: AVPacket = unsafe { mem::zeroed() };
+
+    unsafe {
+        avcodec::av_init_packet(&mut pkt);
+    }
+
+    pkt
+}
+
+// Converts a path to a C string suitable for FFmpeg: raw bytes on Unix.
+#[cfg(unix)]
+fn os_to_cstring(ostr: &OsStr) -> Option<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    CString::new(ostr.as_bytes()).ok()
+}
+
+// Converts a path to a C string suitable for FFmpeg: UTF-8 on Windows.
+//
+// Paths that are not valid UTF-16 (e.g. with unpaired surrogates) cannot be represented and
+// yield `None`.
+#[cfg(windows)]
+fn os_to_cstring(ostr: &OsStr) -> Option<CString> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let wide: Vec<u16> = ostr.encode_wide().collect();
+
+    String::from_utf16(&wide[..]).ok().and_then(|s| CString::new(s).ok())
+}
+
+// The container format to stream to a network URL, or `None` if the path is not a URL of a
+// supported streaming protocol.
+fn stream_format(path: &Path) -> Option<&'static str> {
+    let path = match path.to_str() {
+        Some(path) => path,
+        None       => return None
+    };
+
+    let scheme = match path.find("://") {
+        Some(end) => path[.. end].to_ascii_lowercase(),
+        None      => return None
+    };
+
+    match &scheme[..] {
+        "rtmp" | "rtmps"      => Some("flv"),
+        "udp" | "tcp" | "srt" => Some("mpegts"),
+        _                     => None
+    }
+}
+
+// The path of a segment: `base` with the segment index appended to the file stem.
+fn segment_path(base: &Path, index: usize) -> PathBuf {
+    let mut name = base.file_stem().unwrap_or(OsStr::new("video")).to_os_string();
+    name.push(&format!("_{:03}", index));
+
+    if let Some(ext) = base.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+
+    base.with_file_name(&name)
+}
+
+// Video dimensions must be a multiple of two.
+fn round_up_to_even(n: usize) -> usize {
+    if n % 2 == 0 { n } else { n + 1 }
+}
+
+fn round_up_to_multiple(n: usize, m: usize) -> usize {
+    (n + m - 1) / m * m
+}
+
+// Spreads, in place, the rows of `row_len` bytes of an image to rows of `stride` bytes, and
+// leaves a padding row at the end of the buffer.
+fn pad_rows(vec: &mut Vec<u8>, row_len: usize, height: usize, stride: usize) {
+    vec.truncate(row_len * height);
+
+    let padded_len = stride * (height + 1);
+    vec.extend(iter::repeat(0u8).take(padded_len - row_len * height));
+
+    if stride == row_len {
+        return;
+    }
+
+    // Move the rows from the last one, so that no row is overwritten before being moved.
+    for j in (1..height).rev() {
+        unsafe {
+            ptr::copy(vec.as_ptr().offset((j * row_len) as isize),
+                      vec.as_mut_ptr().offset((j * stride) as isize),
+                      row_len);
+        }
+    }
+}
+
+// Crops, in place, a bottom-to-top RGB24 image to a region given from the top-left corner.
+//
+// The region is clamped to the image. Returns the size of the cropped image.
+#[cfg(feature = "kiss3d")]
+fn crop_rgb(buf:    &mut Vec<u8>,
+            width:  usize,
+            height: usize,
+            region: (usize, usize, usize, usize))
+            -> (usize, usize) {
+    let (x, y, w, h) = region;
+    let x = cmp::min(x, width);
+    let y = cmp::min(y, height);
+    let w = cmp::min(w, width - x);
+    let h = cmp::min(h, height - y);
+
+    // Rows are stored bottom to top, so the bottom row of the region comes first. Each row moves
+    // towards the start of the buffer, so copying forward never overwrites a row not yet moved.
+    for r in 0 .. h {
+        let src = ((height - y - h + r) * width + x) * 3;
+        let dst = r * w * 3;
+
+        for k in 0 .. w * 3 {
+            buf[dst + k] = buf[src + k];
+        }
+    }
+
+    buf.truncate(w * h * 3);
+
+    (w, h)
+}
+
+fn vflip(vec: &mut [u8], width: usize, height: usize) {
+    // Swap whole rows rather than individual bytes.
+    for j in (0..height / 2) {
+        let (top, bottom) = vec.split_at_mut((height - j - 1) * width);
+        let top_row       = &mut top[j * width .. (j + 1) * width];
+        let bottom_row    = &mut bottom[.. width];
+
+        for (a, b) in top_row.iter_mut().zip(bottom_row.iter_mut()) {
+            mem::swap(a, b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::ptr;
+    use std::slice;
+    use std::thread;
+    use std::sync::mpsc;
+    use bench::Bencher;
+    use std::path::{Path, PathBuf};
+    use avutil;
+    use super::{vflip, pad_rows, segment_path};
+    use super::{Backpressure, ProResProfile, Recorder, RecorderBuilder, RecorderError};
+    use libc::c_int;
+    use avcodec;
+    use swscale::{self, Struct_SwsContext};
+    #[cfg(feature = "kiss3d")]
+    use super::crop_rgb;
+    #[cfg(windows)]
+    use std::ffi::OsStr;
+    #[cfg(windows)]
+    use super::os_to_cstring;
+
+    // XXX: this should be defined by the bindings.
+    extern "C" {
+        fn sws_getColorspaceDetails(c: *mut Struct_SwsContext,
+                                    inv_table: *mut *mut c_int, src_range: *mut c_int,
+                                    table: *mut *mut c_int, dst_range: *mut c_int,
+                                    brightness: *mut c_int, contrast: *mut c_int,
+                                    saturation: *mut c_int) -> c_int;
+    }
+
+    fn assert_send<T: Send>() {}
+
+    // A non-seekable writer whose content can be read once the recorder is gone.
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend(buf.iter().cloned());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // An RGB24 image with distinct pixels.
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0 .. width * height * 3).map(|i| (i % 251) as u8).collect()
+    }
+
+    // The byte by byte implementation that `vflip` replaced.
+    fn vflip_bytewise(vec: &mut [u8], width: usize, height: usize) {
+        for j in 0 .. height / 2 {
+            for i in 0 .. width {
+                vec.swap((height - j - 1) * width + i, j * width + i);
+            }
+        }
+    }
+
+    #[test]
+    fn vflip_reverses_the_rows() {
+        let mut image = vec![1, 1, 2, 2, 3, 3];
+
+        vflip(&mut image[..], 2, 3);
+
+        assert_eq!(image, vec![3, 3, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn vflip_matches_the_bytewise_flip() {
+        for &(width, height) in [(1, 1), (3, 2), (7, 5), (12, 8)].iter() {
+            let image: Vec<u8> = (0 .. width * height).map(|i| (i * 7 % 256) as u8).collect();
+            let mut expected   = image.clone();
+            let mut actual     = image.clone();
+
+            vflip_bytewise(&mut expected[..], width, height);
+            vflip(&mut actual[..], width, height);
+
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn recorder_moves_across_threads() {
+        assert_send::<Recorder>();
+
+        let recorder = Recorder::new("moved.mp4", 64, 64);
+
+        thread::spawn(move || drop(recorder)).join().unwrap();
+    }
+
+    #[test]
+    fn pad_rows_spreads_the_rows_to_the_stride() {
+        let mut image = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+        pad_rows(&mut image, 3, 3, 4);
+
+        assert_eq!(image.len(), 4 * 4);
+        assert_eq!(&image[0 .. 3],  &[1, 2, 3][..]);
+        assert_eq!(&image[4 .. 7],  &[4, 5, 6][..]);
+        assert_eq!(&image[8 .. 11], &[7, 8, 9][..]);
+    }
+
+    #[test]
+    fn pad_rows_keeps_aligned_rows() {
+        let mut image = vec![1, 2, 3, 4];
+
+        pad_rows(&mut image, 2, 2, 2);
+
+        assert_eq!(image, vec![1, 2, 3, 4, 0, 0]);
+    }
+
+    #[test]
+    fn snap_rgb_encodes_odd_sized_images() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 0, 0);
+        let image        = gradient(101, 77);
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 101, 77).unwrap();
+        }
+
+        let info = recorder.stream_info().unwrap();
+
+        assert_eq!((info.width, info.height), (102, 78));
+        assert!(!recorder.into_bytes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn snap_rgb_scales_to_the_video_size() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 400, 300);
+        let image        = gradient(800, 600);
+        let (tx, rx)     = mpsc::channel();
+
+        recorder.set_progress_callback(Box::new(move |info| tx.send(info.frame_index).unwrap()));
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 800, 600).unwrap();
+        }
+
+        let info = recorder.stream_info().unwrap();
+
+        assert_eq!((info.width, info.height), (400, 300));
+        assert!(!recorder.into_bytes().unwrap().is_empty());
+
+        // Every scaled frame is encoded.
+        assert_eq!(rx.iter().count(), 5);
+    }
+
+    #[test]
+    fn segment_path_appends_the_index_to_the_stem() {
+        assert_eq!(segment_path(Path::new("out/clip.mp4"), 1), PathBuf::from("out/clip_001.mp4"));
+        assert_eq!(segment_path(Path::new("clip"), 12),        PathBuf::from("clip_012"));
+        assert_eq!(segment_path(Path::new("a/b.tar.gz"), 0),  PathBuf::from("a/b.tar_000.gz"));
+        assert_eq!(segment_path(Path::new("clip.mkv"), 1234),  PathBuf::from("clip_1234.mkv"));
+    }
+
+    // A bottom-to-top RGB24 image whose pixels hold 10 * y + x, from the top-left corner.
+    #[cfg(feature = "kiss3d")]
+    fn labeled_image(width: usize, height: usize) -> Vec<u8> {
+        let mut image = Vec::new();
+
+        for r in 0 .. height {
+            for x in 0 .. width {
+                let label = (10 * (height - 1 - r) + x) as u8;
+
+                image.push(label);
+                image.push(label);
+                image.push(label);
+            }
+        }
+
+        image
+    }
+
+    #[cfg(feature = "kiss3d")]
+    #[test]
+    fn crop_rgb_keeps_the_region_from_the_top_left_corner() {
+        let mut image = labeled_image(3, 3);
+
+        assert_eq!(crop_rgb(&mut image, 3, 3, (1, 0, 2, 2)), (2, 2));
+        assert_eq!(image, vec![11, 11, 11, 12, 12, 12, 1, 1, 1, 2, 2, 2]);
+    }
+
+    #[cfg(feature = "kiss3d")]
+    #[test]
+    fn crop_rgb_clamps_the_region_to_the_image() {
+        let mut image = labeled_image(3, 3);
+
+        assert_eq!(crop_rgb(&mut image, 3, 3, (2, 2, 10, 10)), (1, 1));
+        assert_eq!(image, vec![22, 22, 22]);
+
+        let mut image = labeled_image(3, 3);
+
+        assert_eq!(crop_rgb(&mut image, 3, 3, (5, 0, 1, 1)).0, 0);
+        assert!(image.is_empty());
+    }
+
+    #[test]
+    fn snap_rgb_rejects_buffers_of_the_wrong_length() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 0, 0);
+        let image        = gradient(8, 8);
+
+        match recorder.snap_rgb(&image[.. 100], 8, 8) {
+            Err(RecorderError::IncompleteFrame(expected, actual)) => assert_eq!((expected, actual), (192, 100)),
+            res                                                   => panic!("unexpected result: {:?}", res)
+        }
+
+        match recorder.snap_rgba(&image[..], 8, 8) {
+            Err(RecorderError::IncompleteFrame(expected, actual)) => assert_eq!((expected, actual), (256, 192)),
+            res                                                   => panic!("unexpected result: {:?}", res)
+        }
+
+        assert!(!recorder.is_initialized());
+    }
+
+    #[test]
+    fn snap_depth_rejects_invalid_planes() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 0, 0);
+        let depth        = vec![0.5f32; 16];
+
+        match recorder.snap_depth(&depth[..], 4, 4, 1.0, 1.0) {
+            Err(RecorderError::InvalidArgument(..)) => (),
+            res                                     => panic!("unexpected result: {:?}", res)
+        }
+
+        match recorder.snap_depth(&depth[.. 15], 4, 4, 0.1, 100.0) {
+            Err(RecorderError::IncompleteFrame(16, 15)) => (),
+            res                                         => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn nul_bytes_in_options_are_errors() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 0, 0);
+
+        match recorder.set_codec_option("preset", "fast\0") {
+            Err(RecorderError::NulByte(..)) => (),
+            res                             => panic!("unexpected result: {:?}", res)
+        }
+
+        match recorder.set_metadata("ti\0tle", "clip") {
+            Err(RecorderError::NulByte(..)) => (),
+            res                             => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn snap_rgb_converts_to_the_requested_pixel_format() {
+        let mut recorder = RecorderBuilder::new().mjpeg()
+                                                 .pix_fmt(avutil::PIX_FMT_YUVJ444P)
+                                                 .build_in_memory("avi", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 5 {
+            let outcome = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+
+            // MJPEG frames are encoded independently, without delay.
+            assert!(outcome.packet_written && outcome.bytes > 0);
+        }
+
+        assert_eq!(recorder.stream_info().unwrap().pix_fmt, avutil::PIX_FMT_YUVJ444P);
+        assert!(!recorder.into_bytes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn snap_stereo_rejects_invalid_views() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 0, 0);
+        let view         = gradient(8, 8);
+
+        match recorder.snap_stereo(&view[..], &view[.. 90], 8, 8) {
+            Err(RecorderError::IncompleteFrame(192, 90)) => (),
+            res                                          => panic!("unexpected result: {:?}", res)
+        }
+
+        match recorder.snap_stereo(&[], &[], 0, 8) {
+            Err(RecorderError::InvalidDimensions(0, 8)) => (),
+            res                                         => panic!("unexpected result: {:?}", res)
+        }
+
+        assert!(!recorder.is_initialized());
+    }
+
+    #[test]
+    fn threaded_snap_rgb_rejects_buffers_of_the_wrong_length() {
+        let mut recorder = Recorder::new_threaded("threaded.mp4", 8, 8, 2, Backpressure::Block);
+        let image        = gradient(8, 8);
+
+        match recorder.snap_rgb(&image[.. 10], 8, 8) {
+            Err(RecorderError::IncompleteFrame(192, 10)) => (),
+            res                                          => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn metadata_is_written_to_the_container() {
+        let mut recorder = RecorderBuilder::new().metadata("title", "krecord metadata test")
+                                                 .build_in_memory("matroska", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        let bytes = recorder.into_bytes().unwrap();
+        let title = b"krecord metadata test";
+
+        assert!(bytes.windows(title.len()).any(|w| w == &title[..]));
+    }
+}
+
+    #[test]
+    fn scaling_keeps_the_color_details_when_the_input_changes() {
+        let mut recorder = RecorderBuilder::new().color_range(avcodec::AVCOL_RANGE_JPEG)
+                                                 .colorspace(avcodec::AVCOL_SPC_BT709)
+                                                 .build_in_memory("mpeg", 64, 48);
+
+        for &(width, height) in [(64, 48), (32, 24), (32, 24), (80, 60)].iter() {
+            let _ = recorder.snap_rgb(&gradient(width, height)[..], width, height).unwrap();
+
+            unsafe {
+                assert_eq!((*recorder.context).color_range, avcodec::AVCOL_RANGE_JPEG);
+                assert_eq!((*recorder.context).colorspace,  avcodec::AVCOL_SPC_BT709);
+
+                let mut inv_table  = ptr::null_mut();
+                let mut table      = ptr::null_mut();
+                let mut src_range  = 0;
+                let mut dst_range  = 0;
+                let mut brightness = 0;
+                let mut contrast   = 0;
+                let mut saturation = 0;
+
+                assert!(sws_getColorspaceDetails(recorder.scale_context,
+                                                 &mut inv_table, &mut src_range,
+                                                 &mut table, &mut dst_range,
+                                                 &mut brightness, &mut contrast,
+                                                 &mut saturation) >= 0);
+
+                // The context keeps a copy of the 4 coefficients.
+                let bt709 = swscale::sws_getCoefficients(avcodec::AVCOL_SPC_BT709 as i32);
+
+                assert_eq!(dst_range, 1);
+                assert_eq!(slice::from_raw_parts(table as *const c_int, 4),
+                           slice::from_raw_parts(bt709, 4));
+            }
+        }
+    }
+
+    #[test]
+    fn snap_rgb_scales_resized_inputs_mid_recording() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 320, 240);
+        let (tx, rx)     = mpsc::channel();
+
+        recorder.set_progress_callback(Box::new(move |info| tx.send(info.frame_index).unwrap()));
+
+        for &(width, height) in [(320, 240), (640, 480)].iter() {
+            let image = gradient(width, height);
+
+            for _ in 0 .. 5 {
+                assert!(recorder.snap_rgb(&image[..], width, height).is_ok());
+            }
+        }
+
+        let info = recorder.stream_info().unwrap();
+
+        assert_eq!((info.width, info.height), (320, 240));
+        assert!(!recorder.into_bytes().unwrap().is_empty());
+
+        // Every frame is written once the encoder is flushed, and the callback dropped.
+        assert_eq!(rx.iter().collect::<Vec<_>>(), (0 .. 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn first_packet_is_at_pts_0() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 64, 48);
+        let image        = gradient(64, 48);
+        let (tx, rx)     = mpsc::channel();
+
+        recorder.set_progress_callback(Box::new(move |info| tx.send(info.pts_secs).unwrap()));
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        let _ = recorder.into_bytes().unwrap();
+
+        assert_eq!(rx.iter().next(), Some(0.0));
+    }
+
+    #[test]
+    fn ten_frames_make_a_complete_container() {
+        let mut recorder = Recorder::new_in_memory("mpeg", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 10 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        let bytes = recorder.into_bytes().unwrap();
+
+        // An MPEG program stream starts with a pack header.
+        assert!(bytes.len() > 4);
+        assert_eq!(&bytes[.. 4], &[0x00, 0x00, 0x01, 0xba][..]);
+    }
+
+    #[test]
+    fn drop_writes_the_trailer() {
+        let path  = env::temp_dir().join("krecord_drop_writes_the_trailer.mp4");
+        let image = gradient(64, 48);
+
+        {
+            let mut recorder = Recorder::new(&path, 64, 48);
+
+            for _ in 0 .. 10 {
+                let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+            }
+        }
+
+        let mut bytes = Vec::new();
+
+        let _ = File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // The MP4 index is only written with the trailer.
+        assert_eq!(&bytes[4 .. 8], &b"ftyp"[..]);
+        assert!(bytes.windows(4).any(|w| w == &b"moov"[..]));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn os_to_cstring_encodes_windows_paths_as_utf8() {
+        let path = os_to_cstring(OsStr::new("C:\\vidéos\\clip.mp4")).unwrap();
+
+        assert_eq!(path.as_bytes(), "C:\\vidéos\\clip.mp4".as_bytes());
+    }
+
+    // Records 10 frames of a moving gradient with libx264 at the given Constant Rate Factor.
+    fn record_with_crf(crf: f64) -> Vec<u8> {
+        let mut recorder = RecorderBuilder::new().codec_name("libx264")
+                                                 .crf(crf)
+                                                 .build_in_memory("matroska", 128, 96);
+        let base         = gradient(128, 96);
+
+        for i in 0 .. 10 {
+            let image: Vec<u8> = base.iter().map(|&p| ((p as usize + 7 * i) % 256) as u8).collect();
+
+            let _ = recorder.snap_rgb(&image[..], 128, 96).unwrap();
+        }
+
+        recorder.into_bytes().unwrap()
+    }
+
+    #[test]
+    fn lower_crf_gives_bigger_files() {
+        assert!(record_with_crf(10.0).len() > record_with_crf(40.0).len());
+    }
+
+    #[test]
+    fn gif_output_uses_a_palette_format() {
+        let mut recorder = Recorder::new_in_memory("gif", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        let info = recorder.stream_info().unwrap();
+
+        assert_eq!(info.codec_name, "gif");
+        assert!(info.pix_fmt != avutil::PIX_FMT_YUV420P);
+
+        let bytes = recorder.into_bytes().unwrap();
+
+        assert_eq!(&bytes[.. 4], &b"GIF8"[..]);
+    }
+
+    #[test]
+    fn preserve_alpha_keeps_the_alpha_channel() {
+        let mut recorder = RecorderBuilder::new().codec_name("libvpx-vp9")
+                                                 .preserve_alpha()
+                                                 .build_in_memory("webm", 64, 48);
+        let image: Vec<u8> = (0 .. 64 * 48 * 4).map(|i| if i % 4 == 3 { 128 } else { (i % 251) as u8 })
+                                               .collect();
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgba(&image[..], 64, 48).unwrap();
+        }
+
+        assert_eq!(recorder.stream_info().unwrap().pix_fmt, avutil::PIX_FMT_YUVA420P);
+        assert!(!recorder.into_bytes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn mjpeg_encodes_every_frame_as_a_full_range_keyframe() {
+        let mut recorder = RecorderBuilder::new().mjpeg().build_in_memory("avi", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 5 {
+            let outcome = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+
+            assert!(outcome.packet_written);
+        }
+
+        let info = recorder.stream_info().unwrap();
+
+        assert_eq!(info.pix_fmt, avutil::PIX_FMT_YUVJ420P);
+        assert_eq!((info.gop_size, info.max_b_frames), (0, 0));
+        assert!(!recorder.into_bytes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn prores_records_the_requested_profile() {
+        let mut recorder = RecorderBuilder::new().prores(ProResProfile::Hq).build_in_memory("mov", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        assert_eq!(recorder.stream_info().unwrap().pix_fmt, avutil::PIX_FMT_YUV422P10LE);
+
+        // The sample description holds the fourcc of the profile.
+        let bytes = recorder.into_bytes().unwrap();
+
+        assert!(bytes.windows(4).any(|w| w == &b"apch"[..]));
+    }
+
+    #[test]
+    fn non_seekable_outputs_get_fragmented_mp4() {
+        let data         = Arc::new(Mutex::new(Vec::new()));
+        let mut recorder = Recorder::new_with_writer(SharedWriter(data.clone()), "mp4", 64, 48);
+        let image        = gradient(64, 48);
+
+        for _ in 0 .. 10 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        recorder.finalize().unwrap();
+
+        // Fragments are stored in "moof" boxes, after an empty "moov" one.
+        let bytes = data.lock().unwrap();
+
+        assert_eq!(&bytes[4 .. 8], &b"ftyp"[..]);
+        assert!(bytes.windows(4).any(|w| w == &b"moof"[..]));
+    }
+
+    // Measures the conversion of `in_width x in_height` RGB24 images to the video size and
+    // format, without the encoding.
+    fn bench_conversion(b: &mut Bencher, builder: RecorderBuilder,
+                        in_width: usize, in_height: usize, width: usize, height: usize) {
+        let mut recorder = builder.build_in_memory("mpeg", width, height);
+        let image        = gradient(in_width, in_height);
+
+        recorder.init().unwrap();
+
+        b.iter(|| {
+            recorder.tmp_frame_buf.clear();
+            recorder.tmp_frame_buf.extend(image.iter().cloned());
+            recorder.convert_tmp_frame(in_width, in_height, avutil::PIX_FMT_RGB24, 3).unwrap();
+        });
+    }
+
+    #[bench]
+    fn bench_scale_1080p_fast_bilinear(b: &mut Bencher) {
+        let builder = RecorderBuilder::new().scale_flags(swscale::SWS_FAST_BILINEAR as i32);
+
+        bench_conversion(b, builder, 1920, 1080, 1280, 720);
+    }
+
+    #[bench]
+    fn bench_scale_1080p_bicubic(b: &mut Bencher) {
+        bench_conversion(b, RecorderBuilder::new(), 1920, 1080, 1280, 720);
+    }
+
+    #[bench]
+    fn bench_scale_1080p_lanczos(b: &mut Bencher) {
+        let builder = RecorderBuilder::new().scale_flags(swscale::SWS_LANCZOS as i32);
+
+        bench_conversion(b, builder, 1920, 1080, 1280, 720);
+    }
+
+    #[bench]
+    fn bench_vflip_1080p(b: &mut Bencher) {
+        let mut image = gradient(1920, 1080);
+
+        b.iter(|| vflip(&mut image[..], 1920 * 3, 1080));
+    }
+
+    #[bench]
+    fn bench_vflip_bytewise_1080p(b: &mut Bencher) {
+        let mut image = gradient(1920, 1080);
+
+        b.iter(|| vflip_bytewise(&mut image[..], 1920 * 3, 1080));
+    }
+
+    // Tiny frames, for the cost of each snapshot outside of the conversion and encoding, like
+    // the packet handling.
+    #[bench]
+    fn bench_snap_rgb_small_frames(b: &mut Bencher) {
+        let mut recorder = RecorderBuilder::new().gop_size(0)
+                                                 .max_b_frames(0)
+                                                 .build_in_memory("mpeg", 16, 16);
+        let image        = gradient(16, 16);
+
+        b.iter(|| recorder.snap_rgb(&image[..], 16, 16).unwrap());
+    }
+
+    // The conversion runs on the calling thread, as a single slice.
+    #[bench]
+    fn bench_convert_4k_bicubic(b: &mut Bencher) {
+        bench_conversion(b, RecorderBuilder::new(), 3840, 2160, 3840, 2160);
+    }
+
+    #[bench]
+    fn bench_convert_4k_fast_bilinear(b: &mut Bencher) {
+        let builder = RecorderBuilder::new().scale_flags(swscale::SWS_FAST_BILINEAR as i32);
+
+        bench_conversion(b, builder, 3840, 2160, 3840, 2160);
+    }
+
+    // What remains on the calling thread when the conversion is moved to a worker.
+    #[bench]
+    fn bench_threaded_snap_rgb_4k(b: &mut Bencher) {
+        let path  = env::temp_dir().join("krecord_bench_threaded_snap_rgb_4k.mp4");
+        let image = gradient(3840, 2160);
+
+        {
+            let mut recorder = Recorder::new_threaded(&path, 3840, 2160, 2, Backpressure::DropNewest);
+
+            b.iter(|| recorder.snap_rgb(&image[..], 3840, 2160).unwrap());
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    // Measures the encoding of 720p frames with libx264 and the given number of threads.
+    fn bench_encoder_threads(b: &mut Bencher, threads: usize) {
+        let mut recorder = RecorderBuilder::new().codec_name("libx264")
+                                                 .encoder_threads(threads)
+                                                 .build_in_memory("matroska", 1280, 720);
+        let image        = gradient(1280, 720);
+
+        b.iter(|| recorder.snap_rgb(&image[..], 1280, 720).unwrap());
+    }
+
+    #[bench]
+    fn bench_encode_720p_1_thread(b: &mut Bencher) {
+        bench_encoder_threads(b, 1);
+    }
+
+    #[bench]
+    fn bench_encode_720p_4_threads(b: &mut Bencher) {
+        bench_encoder_threads(b, 4);
+    }
+
+    // The quality reported for each packet of 5 frames recorded with MPEG-1.
+    fn reported_quality(quality_stats: bool) -> Vec<Option<f32>> {
+        let mut recorder = RecorderBuilder::new().enable_quality_stats(quality_stats)
+                                                 .build_in_memory("mpeg", 64, 48);
+        let image        = gradient(64, 48);
+        let (tx, rx)     = mpsc::channel();
+
+        recorder.set_progress_callback(Box::new(move |info| tx.send(info.quality).unwrap()));
+
+        for _ in 0 .. 5 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        let _ = recorder.into_bytes().unwrap();
+
+        rx.iter().collect()
+    }
+
+    #[test]
+    fn quality_is_only_reported_when_enabled() {
+        let disabled = reported_quality(false);
+        let enabled  = reported_quality(true);
+
+        assert_eq!(disabled.len(), 5);
+        assert!(disabled.iter().all(|q| q.is_none()));
+
+        // A lossy encoding of a detailed image is never perfect.
+        assert_eq!(enabled.len(), 5);
+        assert!(enabled.iter().all(|q| q.map_or(false, |q| q.is_finite() && q > 0.0)));
+    }
+
+    #[test]
+    fn frame_limit_spans_the_segments() {
+        let base         = env::temp_dir().join("krecord_frame_limit_spans_the_segments.mpg");
+        let mut recorder = RecorderBuilder::new().fps(10.0).build(&base, 64, 48);
+        let image        = gradient(64, 48);
+
+        recorder.set_segment_duration(1.0).unwrap();
+        recorder.set_frame_limit(15);
+
+        for _ in 0 .. 20 {
+            let _ = recorder.snap_rgb(&image[..], 64, 48).unwrap();
+        }
+
+        // 10 frames in the first segment, the 5 others in the second one.
+        assert!(recorder.is_complete());
+        assert_eq!(recorder.segment_index(), 1);
+        assert_eq!(recorder.frame_count(), 5);
+
+        recorder.finalize().unwrap();
+
+        for index in 0 .. 2 {
+            fs::remove_file(segment_path(&base, index)).unwrap();
+        }
+    }
 }
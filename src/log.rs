@@ -0,0 +1,140 @@
+//! Routing of the FFmpeg diagnostics to a user-defined callback.
+
+use std::mem;
+use std::ptr;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use libc::{c_char, c_int, c_void};
+use avutil;
+use Recorder;
+
+// XXX: those should be defined by the bindings.
+static AV_LOG_PANIC:   c_int = 0;
+static AV_LOG_FATAL:   c_int = 8;
+static AV_LOG_ERROR:   c_int = 16;
+static AV_LOG_WARNING: c_int = 24;
+static AV_LOG_INFO:    c_int = 32;
+static AV_LOG_VERBOSE: c_int = 40;
+static AV_LOG_DEBUG:   c_int = 48;
+
+/// A function receiving the diagnostics of FFmpeg and of the recorder.
+pub type LogCallback = Box<Fn(Level, String) + Send + Sync>;
+
+// The address of the boxed `LogCallback`, or 0 if FFmpeg logs to stderr.
+static CALLBACK: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The severity of a log message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Something went really wrong and the process will crash now.
+    Panic,
+    /// Something went wrong and recovery is not possible.
+    Fatal,
+    /// Something went wrong and cannot losslessly be recovered.
+    Error,
+    /// Something somehow does not look correct.
+    Warning,
+    /// Standard information.
+    Info,
+    /// Detailed information.
+    Verbose,
+    /// Stuff which is only useful for the FFmpeg developers.
+    Debug
+}
+
+impl Level {
+    fn from_av(level: c_int) -> Level {
+        if      level <= AV_LOG_PANIC   { Level::Panic }
+        else if level <= AV_LOG_FATAL   { Level::Fatal }
+        else if level <= AV_LOG_ERROR   { Level::Error }
+        else if level <= AV_LOG_WARNING { Level::Warning }
+        else if level <= AV_LOG_INFO    { Level::Info }
+        else if level <= AV_LOG_VERBOSE { Level::Verbose }
+        else                            { Level::Debug }
+    }
+
+    fn to_av(self) -> c_int {
+        match self {
+            Level::Panic   => AV_LOG_PANIC,
+            Level::Fatal   => AV_LOG_FATAL,
+            Level::Error   => AV_LOG_ERROR,
+            Level::Warning => AV_LOG_WARNING,
+            Level::Info    => AV_LOG_INFO,
+            Level::Verbose => AV_LOG_VERBOSE,
+            Level::Debug   => AV_LOG_DEBUG
+        }
+    }
+}
+
+impl Recorder {
+    /// Routes the diagnostics of FFmpeg and the non-fatal events of the recorder (clamped
+    /// settings, dropped frames, rebuilt scaling contexts) to `callback`.
+    ///
+    /// FFmpeg has a single log callback for the whole process, so this affects every recorder,
+    /// including those already created. Only messages at or below the FFmpeg log level (`Info`
+    /// by default) are forwarded. The callback may be called from any thread, including the
+    /// FFmpeg worker threads, and must not panic. A callback set by a previous call is replaced,
+    /// but never freed.
+    pub fn set_log_callback(callback: LogCallback) {
+        let callback: Box<LogCallback> = Box::new(callback);
+        let callback: usize = unsafe { mem::transmute(callback) };
+
+        let _ = CALLBACK.swap(callback, Ordering::SeqCst);
+
+        unsafe {
+            avutil::av_log_set_callback(Some(log_callback));
+        }
+    }
+
+    /// Restores the default FFmpeg logging, to the standard error output.
+    pub fn clear_log_callback() {
+        let _ = CALLBACK.swap(0, Ordering::SeqCst);
+
+        unsafe {
+            avutil::av_log_set_callback(Some(avutil::av_log_default_callback));
+        }
+    }
+}
+
+/// Emits a message of the recorder through the FFmpeg logging system.
+pub fn log(level: Level, msg: &str) {
+    let fmt = CString::new(&b"[kiss3d_recording] %s\n"[..]).unwrap();
+    let msg = CString::new(msg.replace("\0", "")).unwrap();
+
+    unsafe {
+        avutil::av_log(ptr::null_mut(), level.to_av(), fmt.as_ptr(), msg.as_ptr());
+    }
+}
+
+/// Emits a non-fatal warning of the recorder.
+pub fn warn(msg: &str) {
+    log(Level::Warning, msg)
+}
+
+extern "C" fn log_callback(avcl: *mut c_void, level: c_int, fmt: *const c_char, vl: avutil::va_list) {
+    unsafe {
+        let callback = CALLBACK.load(Ordering::SeqCst) as *const LogCallback;
+
+        if callback.is_null() {
+            avutil::av_log_default_callback(avcl, level, fmt, vl);
+            return;
+        }
+
+        if level > avutil::av_log_get_level() {
+            return;
+        }
+
+        let mut line         = [0 as c_char; 1024];
+        let mut print_prefix = 1;
+
+        avutil::av_log_format_line(avcl, level, fmt, vl, line.as_mut_ptr(), line.len() as c_int,
+                                   &mut print_prefix);
+
+        let msg = String::from_utf8_lossy(CStr::from_ptr(line.as_ptr()).to_bytes());
+        let msg = msg.trim_right();
+
+        if !msg.is_empty() {
+            (*callback)(Level::from_av(level), msg.to_string());
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Recording into an in-memory buffer, or any writer, instead of a file.
+
+use std::ptr;
+use std::slice;
+use std::iter;
+use std::io::{self, Write};
+use libc::{c_int, c_void, EIO, SEEK_SET, SEEK_CUR, SEEK_END};
+use avutil;
+use avformat::{self, AVIOContext};
+use {Recorder, RecorderBuilder, RecorderError};
+
+const AVIO_BUFFER_SIZE: usize = 4096;
+const AVSEEK_SIZE:      c_int = 0x10000; // XXX: this should be defined by the bindings.
+const AVSEEK_FORCE:     c_int = 0x20000; // XXX: this should be defined by the bindings.
+
+/// An output other than a file.
+pub enum CustomOutput {
+    /// A seekable in-memory buffer, with the current position.
+    Memory(Vec<u8>, usize),
+    /// A non-seekable writer, e.g. the standard input of a child process.
+    Writer(Box<Write + Send>)
+}
+
+impl CustomOutput {
+    /// Creates an empty in-memory output.
+    pub fn new() -> CustomOutput {
+        CustomOutput::Memory(Vec::new(), 0)
+    }
+
+    /// The content written so far, or `None` for writers.
+    pub fn into_data(self) -> Option<Vec<u8>> {
+        match self {
+            CustomOutput::Memory(data, _) => Some(data),
+            CustomOutput::Writer(_)       => None
+        }
+    }
+
+    /// Whether FFmpeg can seek into the output, e.g. to write an MP4 index at the beginning.
+    pub fn is_seekable(&self) -> bool {
+        match *self {
+            CustomOutput::Memory(..) => true,
+            CustomOutput::Writer(_)  => false
+        }
+    }
+
+    /// Flushes the writer, if any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            CustomOutput::Memory(..)        => Ok(()),
+            CustomOutput::Writer(ref mut w) => w.flush()
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        match *self {
+            CustomOutput::Memory(ref mut data, ref mut pos) => {
+                if *pos > data.len() {
+                    let pad = *pos - data.len();
+                    data.extend(iter::repeat(0u8).take(pad));
+                }
+
+                let overlap = ::std::cmp::min(data.len() - *pos, buf.len());
+
+                for (d, s) in data[*pos .. *pos + overlap].iter_mut().zip(buf.iter()) {
+                    *d = *s;
+                }
+
+                data.extend(buf[overlap ..].iter().cloned());
+                *pos = *pos + buf.len();
+
+                Ok(())
+            },
+            CustomOutput::Writer(ref mut w) => w.write_all(buf)
+        }
+    }
+}
+
+impl Recorder {
+    /// Creates a new video recorder writing into memory instead of a file.
+    ///
+    /// The container format cannot be guessed from a path, so it must be given by name, e.g.
+    /// "mp4", "matroska" or "webm". Use `into_bytes` to finish the recording and retrieve the
+    /// video.
+    ///
+    /// # Arguments:
+    /// * `format` - name of the container format.
+    /// * `width`  - width of the recorded video.
+    /// * `height` - height of the recorded video.
+    pub fn new_in_memory(format: &str, width: usize, height: usize) -> Recorder {
+        RecorderBuilder::new().build_in_memory(format, width, height)
+    }
+
+    /// Finishes the recording and returns the content of the video.
+    ///
+    /// This does the same as `finalize`. Fails with `RecorderError::NotInMemory` if the recorder
+    /// does not write into memory; the recording is then finished as on drop.
+    pub fn into_bytes(mut self) -> Result<Vec<u8>, RecorderError> {
+        let in_memory = match self.custom_output {
+            Some(ref output) => output.is_seekable(),
+            None             => false
+        };
+
+        if !in_memory {
+            return Err(RecorderError::NotInMemory);
+        }
+
+        try!(self.finish());
+
+        Ok(self.custom_output.take().unwrap().into_data().unwrap())
+    }
+
+    /// Creates a new video recorder writing into `writer` instead of a file, e.g. the standard
+    /// input of a child process like `ffplay -` or an upload tool.
+    ///
+    /// The container format must be given by name, as for `new_in_memory`. The writer cannot
+    /// seek, so the format must be streamable, e.g. "matroska", "mpegts", "flv" or "nut"; "mp4"
+    /// and "mov" are written as fragmented MP4. The writer is flushed by `finalize`. Errors of the
+    /// writer make the snapshots, or `finalize`, fail.
+    ///
+    /// # Arguments:
+    /// * `writer` - the destination of the encoded video.
+    /// * `format` - name of the container format.
+    /// * `width`  - width of the recorded video.
+    /// * `height` - height of the recorded video.
+    pub fn new_with_writer<W>(writer: W, format: &str, width: usize, height: usize) -> Recorder
+        where W: Write + Send + 'static {
+        RecorderBuilder::new().build_with_writer(writer, format, width, height)
+    }
+}
+
+impl RecorderBuilder {
+    /// Creates a video recorder writing into memory instead of a file.
+    ///
+    /// See `Recorder::new_in_memory` for the meaning of the arguments. Panics if
+    /// `segment_duration_secs` was set.
+    pub fn build_in_memory(&self, format: &str, width: usize, height: usize) -> Recorder {
+        let mut recorder = self.build("", width, height);
+
+        assert!(recorder.segment_duration.is_none(), "Segmented recordings cannot be written into memory.");
+
+        recorder.format_name   = Some(format.to_string());
+        recorder.custom_output = Some(Box::new(CustomOutput::new()));
+
+        recorder
+    }
+
+    /// Creates a video recorder writing into `writer` instead of a file.
+    ///
+    /// See `Recorder::new_with_writer` for the meaning of the arguments. Panics if
+    /// `segment_duration_secs` was set.
+    pub fn build_with_writer<W>(&self, writer: W, format: &str, width: usize, height: usize) -> Recorder
+        where W: Write + Send + 'static {
+        let mut recorder = self.build("", width, height);
+
+        assert!(recorder.segment_duration.is_none(), "Segmented recordings cannot be written into a writer.");
+
+        recorder.format_name   = Some(format.to_string());
+        recorder.custom_output = Some(Box::new(CustomOutput::Writer(Box::new(writer))));
+
+        recorder
+    }
+}
+
+/// Creates an AVIO context writing into `output`.
+///
+/// `output` must neither move nor be freed before the context is freed with
+/// `free_avio_context`. Returns null on allocation failure. Without a seek callback, FFmpeg
+/// marks writers as non-seekable.
+pub unsafe fn alloc_avio_context(output: *mut CustomOutput) -> *mut AVIOContext {
+    let buffer = avutil::av_malloc(AVIO_BUFFER_SIZE as u64) as *mut u8;
+
+    if buffer.is_null() {
+        return ptr::null_mut();
+    }
+
+    let seek_callback: Option<extern "C" fn(*mut c_void, i64, c_int) -> i64> =
+        if (*output).is_seekable() { Some(seek) } else { None };
+
+    let pb = avformat::avio_alloc_context(buffer, AVIO_BUFFER_SIZE as c_int, 1, output as *mut c_void,
+                                          None, Some(write_packet), seek_callback);
+
+    if pb.is_null() {
+        avutil::av_free(buffer as *mut c_void);
+    }
+
+    pb
+}
+
+/// Flushes and frees an AVIO context created by `alloc_avio_context`.
+///
+/// Returns the last write error of the context, or 0.
+pub unsafe fn free_avio_context(pb: *mut AVIOContext) -> c_int {
+    avformat::avio_flush(pb);
+
+    let error = (*pb).error;
+
+    // The buffer may have been reallocated by FFmpeg, so free the current one.
+    avutil::av_free((*pb).buffer as *mut c_void);
+    avutil::av_free(pb as *mut c_void);
+
+    error
+}
+
+extern "C" fn write_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let output = unsafe { &mut *(opaque as *mut CustomOutput) };
+    let buf    = unsafe { slice::from_raw_parts(buf as *const u8, buf_size as usize) };
+
+    match output.write(buf) {
+        Ok(())  => buf_size,
+        // AVERROR(errno), falling back to AVERROR(EIO) for errors without an OS code.
+        Err(e) => -e.raw_os_error().unwrap_or(EIO)
+    }
+}
+
+extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let output = unsafe { &mut *(opaque as *mut CustomOutput) };
+
+    let (data, pos) = match *output {
+        CustomOutput::Memory(ref data, ref mut pos) => (data, pos),
+        CustomOutput::Writer(_)                     => return -1
+    };
+
+    let new_pos = match whence & !AVSEEK_FORCE {
+        AVSEEK_SIZE => return data.len() as i64,
+        SEEK_SET    => offset,
+        SEEK_CUR    => *pos as i64 + offset,
+        SEEK_END    => data.len() as i64 + offset,
+        _           => return -1
+    };
+
+    if new_pos < 0 {
+        return -1;
+    }
+
+    *pos = new_pos as usize;
+
+    new_pos
+}
@@ -0,0 +1,78 @@
+//! Recording of the same images into several outputs at once.
+
+#[cfg(feature = "kiss3d")]
+use kiss3d::window::Window;
+use {Recorder, RecorderError};
+
+/// A set of video recorders fed with the same images, e.g. to record a high quality archive
+/// and a low bit rate preview in a single pass.
+///
+/// The window is read only once per snapshot. Each recorder then copies, flips, scales and
+/// encodes the image on its own, with its own settings and output. The whole window is recorded:
+/// recorders with a capture region fail with `RecorderError::CaptureRegionUnsupported`.
+///
+/// Errors do not stop the other recorders: every recorder gets each image, and the failures are
+/// reported together as `RecorderError::MultipleOutputs`.
+pub struct MultiRecorder {
+    recorders: Vec<Recorder>,
+    buf:       Vec<u8>
+}
+
+impl MultiRecorder {
+    /// Creates a recorder feeding each of `recorders`.
+    pub fn new(recorders: Vec<Recorder>) -> MultiRecorder {
+        MultiRecorder {
+            recorders: recorders,
+            buf:       Vec::new()
+        }
+    }
+
+    /// The recorders, e.g. to query their progress or change their settings.
+    pub fn recorders_mut(&mut self) -> &mut [Recorder] {
+        &mut self.recorders[..]
+    }
+
+    /// Captures an image from the window and adds it to every video.
+    #[cfg(feature = "kiss3d")]
+    pub fn snap(&mut self, window: &Window) -> Result<(), RecorderError> {
+        let (width, height) = try!(::snap_window(window, &mut self.buf));
+
+        let recorders = &mut self.recorders;
+        let buf       = &self.buf[..];
+
+        collect_errors(recorders.iter_mut().map(|r| {
+            if r.capture_region.is_some() {
+                Err(RecorderError::CaptureRegionUnsupported)
+            }
+            else {
+                r.snap_rgb(buf, width, height).map(|_| ())
+            }
+        }))
+    }
+
+    /// Adds an RGB image to every video.
+    ///
+    /// `data` must be tightly packed RGB24, as for `Recorder::snap_rgb`.
+    pub fn snap_rgb(&mut self, data: &[u8], width: usize, height: usize) -> Result<(), RecorderError> {
+        collect_errors(self.recorders.iter_mut().map(|r| r.snap_rgb(data, width, height).map(|_| ())))
+    }
+
+    /// Finishes every recording, even if some fail.
+    pub fn finalize(self) -> Result<(), RecorderError> {
+        collect_errors(self.recorders.into_iter().map(|r| r.finalize()))
+    }
+}
+
+// Gathers the failures of the recorders, in order.
+fn collect_errors<I: Iterator<Item = Result<(), RecorderError>>>(results: I) -> Result<(), RecorderError> {
+    let errors: Vec<(usize, RecorderError)> = results.enumerate()
+                                                     .filter_map(|(i, res)| res.err().map(|e| (i, e)))
+                                                     .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(RecorderError::MultipleOutputs(errors))
+    }
+}
@@ -0,0 +1,184 @@
+//! Burn-in of the frame index and timestamp into the captured images.
+
+use std::iter;
+use avutil;
+
+// The size of a glyph of the font, in font pixels, and of a font pixel, in image pixels.
+const GLYPH_WIDTH:  usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const GLYPH_SCALE:  usize = 2;
+// The blank space around the text, and between glyphs, in font pixels.
+const MARGIN:       usize = 1;
+
+/// Where and in which color to draw the timestamp.
+#[derive(Clone, Copy, Debug)]
+pub struct TimestampOverlay {
+    /// The position of the top-left corner of the text, from the top-left corner of the image.
+    pub x:     usize,
+    /// See `x`.
+    pub y:     usize,
+    /// The color of the text.
+    pub color: (u8, u8, u8)
+}
+
+impl TimestampOverlay {
+    /// Draws the frame index and the elapsed seconds, e.g. "#120 2.000s", on a black background.
+    ///
+    /// `buf` holds the image with rows ordered top to bottom, in pixel format `pix_fmt`, one of
+    /// those accepted by the `snap_*` methods. The text is clipped to the image.
+    pub fn draw(&self, buf: &mut [u8], width: usize, height: usize, pix_fmt: i32, frame_index: usize, secs: f64) {
+        let text       = format!("#{} {:.3}s", frame_index, secs);
+        let foreground = pixel_bytes(pix_fmt, self.color);
+        let background = pixel_bytes(pix_fmt, (0, 0, 0));
+        let bpp        = foreground.len();
+
+        let cols = text.len() * (GLYPH_WIDTH + MARGIN) + MARGIN;
+        let rows = GLYPH_HEIGHT + 2 * MARGIN;
+
+        for row in 0 .. rows * GLYPH_SCALE {
+            for col in 0 .. cols * GLYPH_SCALE {
+                let (x, y) = (self.x + col, self.y + row);
+
+                if x >= width || y >= height {
+                    continue;
+                }
+
+                let color = if is_lit(text.as_bytes(), col / GLYPH_SCALE, row / GLYPH_SCALE) {
+                    &foreground
+                }
+                else {
+                    &background
+                };
+
+                let offset = (y * width + x) * bpp;
+
+                for (d, s) in buf[offset .. offset + bpp].iter_mut().zip(color.iter()) {
+                    *d = *s;
+                }
+            }
+        }
+    }
+}
+
+// Whether the font pixel at (`col`, `row`) of the text box is part of a glyph.
+fn is_lit(text: &[u8], col: usize, row: usize) -> bool {
+    if col < MARGIN || row < MARGIN || row >= MARGIN + GLYPH_HEIGHT {
+        return false;
+    }
+
+    let index = (col - MARGIN) / (GLYPH_WIDTH + MARGIN);
+    let x     = (col - MARGIN) % (GLYPH_WIDTH + MARGIN);
+
+    if index >= text.len() || x >= GLYPH_WIDTH {
+        return false;
+    }
+
+    glyph(text[index])[row - MARGIN] & (1 << (GLYPH_WIDTH - 1 - x)) != 0
+}
+
+// The rows of a glyph, top to bottom, with the leftmost pixel in the highest bit.
+fn glyph(c: u8) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        b'0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        b'1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        b'2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        b'3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        b'4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        b'5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        b'6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        b'7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        b'8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        b'9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        b'.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        b'#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        b's' => [0b000, 0b011, 0b100, 0b001, 0b110],
+        b'-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _    => [0b000; GLYPH_HEIGHT]
+    }
+}
+
+// The bytes of a pixel of the given color.
+fn pixel_bytes(pix_fmt: i32, (r, g, b): (u8, u8, u8)) -> Vec<u8> {
+    if pix_fmt == avutil::PIX_FMT_GRAY8 {
+        // BT.601 luma.
+        vec![((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8]
+    }
+    else if pix_fmt == avutil::PIX_FMT_RGBA {
+        vec![r, g, b, 255]
+    }
+    else if pix_fmt == ::PIX_FMT_RGB48_NATIVE {
+        // Both bytes of a 16-bit channel are equal, so the endianness does not matter.
+        [r, g, b].iter().flat_map(|c| iter::repeat(*c).take(2)).collect()
+    }
+    else {
+        vec![r, g, b]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use avutil;
+    use super::{is_lit, TimestampOverlay};
+
+    // The pixel at (`x`, `y`) of a top-to-bottom image.
+    fn pixel(buf: &[u8], width: usize, bpp: usize, x: usize, y: usize) -> &[u8] {
+        let offset = (y * width + x) * bpp;
+
+        &buf[offset .. offset + bpp]
+    }
+
+    #[test]
+    fn is_lit_follows_the_glyphs() {
+        // The top row of "1" is 0b010, after a margin of one font pixel.
+        assert!(!is_lit(b"1", 0, 1));
+        assert!(!is_lit(b"1", 1, 1));
+        assert!(is_lit(b"1", 2, 1));
+        assert!(!is_lit(b"1", 3, 1));
+        // The margins above the text and between glyphs are blank.
+        assert!(!is_lit(b"11", 2, 0));
+        assert!(!is_lit(b"11", 4, 1));
+        assert!(is_lit(b"11", 6, 1));
+    }
+
+    #[test]
+    fn draw_blits_the_text_on_a_background() {
+        let overlay = TimestampOverlay { x: 0, y: 0, color: (255, 0, 0) };
+        let mut buf = vec![7u8; 100 * 20 * 3];
+
+        overlay.draw(&mut buf[..], 100, 20, avutil::PIX_FMT_RGB24, 0, 0.0);
+
+        // The margin, then the top-left font pixel of "#", two image pixels wide.
+        assert_eq!(pixel(&buf[..], 100, 3, 0, 0), &[0, 0, 0][..]);
+        assert_eq!(pixel(&buf[..], 100, 3, 2, 2), &[255, 0, 0][..]);
+        assert_eq!(pixel(&buf[..], 100, 3, 3, 3), &[255, 0, 0][..]);
+        // Below the text box.
+        assert_eq!(pixel(&buf[..], 100, 3, 0, 19), &[7, 7, 7][..]);
+    }
+
+    #[test]
+    fn draw_clips_the_text_to_the_image() {
+        let overlay = TimestampOverlay { x: 2, y: 2, color: (255, 255, 255) };
+        let mut buf = vec![7u8; 4 * 4 * 3];
+
+        overlay.draw(&mut buf[..], 4, 4, avutil::PIX_FMT_RGB24, 12, 3.5);
+
+        assert_eq!(pixel(&buf[..], 4, 3, 1, 1), &[7, 7, 7][..]);
+        assert_eq!(pixel(&buf[..], 4, 3, 2, 2), &[0, 0, 0][..]);
+    }
+
+    #[test]
+    fn draw_converts_the_color_to_the_pixel_format() {
+        let overlay = TimestampOverlay { x: 0, y: 0, color: (255, 255, 255) };
+        let mut buf = vec![7u8; 40 * 20];
+
+        overlay.draw(&mut buf[..], 40, 20, avutil::PIX_FMT_GRAY8, 0, 0.0);
+
+        assert_eq!(pixel(&buf[..], 40, 1, 2, 2), &[255][..]);
+
+        let mut buf = vec![7u8; 40 * 20 * 4];
+
+        overlay.draw(&mut buf[..], 40, 20, avutil::PIX_FMT_RGBA, 0, 0.0);
+
+        assert_eq!(pixel(&buf[..], 40, 4, 2, 2), &[255, 255, 255, 255][..]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Progress reporting of a recording.
+
+use Recorder;
+
+/// The progress of a recording, reported after each packet written to the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgressInfo {
+    /// The index of the packet just written. Encoders delaying frames emit their first packets a
+    /// few snapshots late, so this may lag behind `Recorder::frame_count`.
+    pub frame_index:   usize,
+    /// The presentation time of the packet just written, in seconds.
+    pub pts_secs:      f64,
+    /// The total size of the encoded packets written so far, in bytes. The container overhead is
+    /// not included.
+    pub bytes_written: u64,
+    /// The PSNR of the luma plane of the encoded frame, in dB, if enabled with
+    /// `RecorderBuilder::enable_quality_stats`. Higher is better; it is infinite for lossless
+    /// frames.
+    pub quality:       Option<f32>
+}
+
+/// What a snapshot wrote to the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SnapOutcome {
+    /// Whether an encoded packet was written. This is `false` when the encoder held the frame
+    /// back, and when the snapshot was skipped because the recording is paused or paced.
+    pub packet_written: bool,
+    /// The size of the packets written, in bytes.
+    pub bytes:          usize
+}
+
+/// A function called with the progress of a recording.
+pub type ProgressCallback = Box<FnMut(ProgressInfo) + Send>;
+
+impl Recorder {
+    /// Calls `callback` after each packet written to the output, e.g. to display a progress bar.
+    ///
+    /// The callback runs on the thread that calls `snap` or `finalize`. It replaces any callback
+    /// set previously.
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
+        self.progress_callback = Some(callback);
+    }
+
+    /// Stops reporting the progress.
+    pub fn clear_progress_callback(&mut self) {
+        self.progress_callback = None;
+    }
+}
@@ -0,0 +1,113 @@
+//! Single-image snapshots of a window.
+
+use std::ptr;
+use std::slice;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use libc::c_void;
+use avcodec;
+use avutil::{self, Struct_AVRational};
+use kiss3d::window::Window;
+use error::ffmpeg_error_string;
+use RecorderError;
+
+/// Returns the current content of the window as an RGB image, with its width and height.
+///
+/// The image is tightly packed RGB24, with rows ordered top to bottom, as most image libraries
+/// expect. This is the reverse of the OpenGL order taken by `Recorder::snap_rgb`. Its size is
+/// that of the framebuffer, larger than the window on HiDPI displays. Fails with
+/// `RecorderError::IncompleteFrame` if the window has not rendered yet.
+pub fn grab_rgb(window: &Window) -> Result<(Vec<u8>, usize, usize), RecorderError> {
+    let mut data = Vec::new();
+
+    let (width, height) = try!(::snap_window(window, &mut data));
+
+    ::vflip(&mut data[..], width * 3, height);
+
+    Ok((data, width, height))
+}
+
+/// Saves the current content of the window to a PNG image.
+///
+/// This is a lightweight alternative to the video recorder when only a still image is needed.
+pub fn screenshot(window: &Window, path: &Path) -> Result<(), RecorderError> {
+    let (data, width, height) = try!(grab_rgb(window));
+
+    let png      = try!(encode_png(&data[..], width, height));
+    let mut file = try!(File::create(path));
+
+    try!(file.write_all(&png[..]));
+
+    Ok(())
+}
+
+// Encodes a tightly packed, top-to-bottom RGB24 image to PNG.
+fn encode_png(data: &[u8], width: usize, height: usize) -> Result<Vec<u8>, RecorderError> {
+    ::register_all();
+
+    unsafe {
+        let codec = avcodec::avcodec_find_encoder(avcodec::AV_CODEC_ID_PNG);
+
+        if codec.is_null() {
+            return Err(RecorderError::CodecNotFound(avcodec::AV_CODEC_ID_PNG as i32));
+        }
+
+        let context = avcodec::avcodec_alloc_context3(codec);
+
+        if context.is_null() {
+            return Err(RecorderError::ContextAllocation);
+        }
+
+        (*context).width     = width  as i32;
+        (*context).height    = height as i32;
+        (*context).pix_fmt   = avutil::PIX_FMT_RGB24;
+        (*context).time_base = Struct_AVRational { num: 1, den: 1 };
+
+        let ret = avcodec::avcodec_open2(context, codec, ptr::null_mut());
+
+        if ret < 0 {
+            avutil::av_free(context as *mut c_void);
+            return Err(RecorderError::CodecOpen(ret));
+        }
+
+        let mut frame = avutil::av_frame_alloc();
+
+        if frame.is_null() {
+            let _ = avcodec::avcodec_close(context);
+            avutil::av_free(context as *mut c_void);
+            return Err(RecorderError::FrameAllocation);
+        }
+
+        (*frame).format = avutil::PIX_FMT_RGB24;
+        (*frame).width  = width  as i32;
+        (*frame).height = height as i32;
+
+        let _ = avcodec::avpicture_fill(frame as *mut avcodec::AVPicture,
+                                        data.as_ptr(),
+                                        avutil::PIX_FMT_RGB24,
+                                        width  as i32,
+                                        height as i32);
+
+        let mut pkt        = ::new_packet();
+        let mut got_output = 0;
+        let ret            = avcodec::avcodec_encode_video2(context, &mut pkt, frame, &mut got_output);
+
+        let res = if ret < 0 {
+            Err(RecorderError::FrameEncoding(ret, ffmpeg_error_string(ret)))
+        }
+        else if got_output == 0 {
+            Err(RecorderError::FrameEncoding(ret, "the encoder produced no image".to_string()))
+        }
+        else {
+            Ok(slice::from_raw_parts(pkt.data as *const u8, pkt.size as usize).to_vec())
+        };
+
+        avcodec::av_free_packet(&mut pkt);
+        avutil::av_frame_free(&mut frame);
+        let _ = avcodec::avcodec_close(context);
+        avutil::av_free(context as *mut c_void);
+
+        res
+    }
+}
@@ -0,0 +1,195 @@
+//! Encoding of image sequences saved on disk.
+
+use std::fs;
+use std::ascii::AsciiExt;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use image::{self, GenericImage};
+use {Recorder, RecorderBuilder, RecorderError};
+
+// The extensions of the images read from a directory.
+const IMAGE_EXTENSIONS: [&'static str; 6] = ["png", "jpg", "jpeg", "bmp", "tga", "tif"];
+
+impl Recorder {
+    /// Encodes every image of a directory into a video, e.g. frames saved as PNG files by an
+    /// offline renderer.
+    ///
+    /// The images are sorted in natural order, so "frame2.png" comes before "frame10.png". They
+    /// must all have the size of the first one, which is the size of the video; otherwise this
+    /// fails with `RecorderError::InconsistentFrameSize`. Files without an image extension are
+    /// skipped.
+    ///
+    /// # Arguments:
+    /// * `dir`  - the directory holding the images.
+    /// * `path` - path to the output file.
+    /// * `fps`  - the frame rate of the video.
+    pub fn from_image_sequence<D, P>(dir: &D, path: &P, fps: f64) -> Result<(), RecorderError>
+        where D: ?Sized + AsRef<OsStr>,
+              P: ?Sized + AsRef<OsStr> {
+        RecorderBuilder::new().fps(fps).record_image_sequence(dir, path)
+    }
+}
+
+impl RecorderBuilder {
+    /// Encodes every image of a directory into a video.
+    ///
+    /// See `Recorder::from_image_sequence` for the details; the frame rate is the one of the
+    /// builder.
+    pub fn record_image_sequence<D, P>(&self, dir: &D, path: &P) -> Result<(), RecorderError>
+        where D: ?Sized + AsRef<OsStr>,
+              P: ?Sized + AsRef<OsStr> {
+        let dir   = Path::new(dir);
+        let files = try!(image_files(dir));
+
+        if files.is_empty() {
+            return Err(RecorderError::EmptyImageSequence(dir.to_path_buf()));
+        }
+
+        let mut recorder = self.build(path, 0, 0);
+        let mut size     = None;
+
+        for file in files.iter() {
+            let img = match image::open(file) {
+                Ok(img) => img.to_rgb(),
+                Err(e)  => return Err(RecorderError::ImageDecoding(file.clone(), e.to_string()))
+            };
+
+            let (width, height) = img.dimensions();
+            let (width, height) = (width as usize, height as usize);
+
+            match size {
+                Some(s) if s != (width, height) =>
+                    return Err(RecorderError::InconsistentFrameSize(file.clone(), width, height)),
+                _ => size = Some((width, height))
+            }
+
+            // Image files are stored top to bottom, unlike OpenGL framebuffers.
+            let mut data = img.into_raw();
+            ::vflip(&mut data[..], width * 3, height);
+
+            let _ = try!(recorder.snap_rgb(&data[..], width, height));
+        }
+
+        recorder.finalize()
+    }
+}
+
+// The image files of a directory, in natural order.
+fn image_files(dir: &Path) -> Result<Vec<PathBuf>, RecorderError> {
+    let mut files = Vec::new();
+
+    for entry in try!(fs::read_dir(dir)) {
+        let path = try!(entry).path();
+
+        let is_image = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => IMAGE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)),
+            None      => false
+        };
+
+        if is_image {
+            files.push(path);
+        }
+    }
+
+    files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    Ok(files)
+}
+
+// Compares two strings, with the digit runs compared by numeric value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().cloned(), b.peek().cloned()) {
+            (None, None)    => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) => {
+                if ca.is_digit(10) && cb.is_digit(10) {
+                    let na = take_digits(&mut a);
+                    let nb = take_digits(&mut b);
+
+                    // Compare the values, i-e, the digits without leading zeros, by length first.
+                    let va = na.trim_left_matches('0');
+                    let vb = nb.trim_left_matches('0');
+
+                    let ord = match va.len().cmp(&vb.len()) {
+                        Ordering::Equal => va.cmp(vb),
+                        ord             => ord
+                    };
+
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+
+                    // Same value: fewer leading zeros first.
+                    if na.len() != nb.len() {
+                        return na.len().cmp(&nb.len());
+                    }
+                }
+                else {
+                    if ca != cb {
+                        return ca.cmp(&cb);
+                    }
+
+                    let _ = a.next();
+                    let _ = b.next();
+                }
+            }
+        }
+    }
+}
+
+// Consumes a run of digits.
+fn take_digits<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> String {
+    let mut digits = String::new();
+
+    while let Some(c) = chars.peek().cloned() {
+        if !c.is_digit(10) {
+            break;
+        }
+
+        digits.push(c);
+        let _ = chars.next();
+    }
+
+    digits
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+    use super::natural_cmp;
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("frame2.png", "frame10.png"), Ordering::Less);
+        assert_eq!(natural_cmp("frame10.png", "frame9.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("frame10.png", "frame10.png"), Ordering::Equal);
+    }
+
+    #[test]
+    fn natural_cmp_puts_fewer_leading_zeros_first() {
+        assert_eq!(natural_cmp("frame007.png", "frame8.png"), Ordering::Less);
+        assert_eq!(natural_cmp("frame7.png", "frame007.png"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_compares_text_by_character() {
+        assert_eq!(natural_cmp("a1.png", "b1.png"), Ordering::Less);
+        assert_eq!(natural_cmp("frame", "frame1"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_sorts_a_sequence() {
+        let mut names = vec!["f10.png", "f2.png", "f1.png", "f20.png", "f3.png"];
+
+        names.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(names, vec!["f1.png", "f2.png", "f3.png", "f10.png", "f20.png"]);
+    }
+}
@@ -0,0 +1,49 @@
+//! Recording of a whole kiss3d render loop.
+
+use std::ffi::OsStr;
+use kiss3d::window::Window;
+use {RecorderBuilder, RecorderError};
+
+/// Tells `record_session` whether to keep recording after a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// Render and record the next frame.
+    Continue,
+    /// Stop the recording, and finalize the video.
+    Stop
+}
+
+/// Runs the render loop of a window and records every frame, with the given settings.
+///
+/// See `RecorderBuilder::record_session`.
+pub fn record_session<P, F>(window: &mut Window, path: &P, opts: &RecorderBuilder, frame_cb: F)
+                            -> Result<(), RecorderError>
+    where P: ?Sized + AsRef<OsStr>,
+          F: FnMut(&mut Window, usize) -> Control {
+    opts.record_session(window, path, frame_cb)
+}
+
+impl RecorderBuilder {
+    /// Runs the render loop of a window and records every frame.
+    ///
+    /// This replaces the usual `while window.render() { ... }` loop: `frame_cb` is called with
+    /// the window and the index of the frame to update the scene, then the frame is rendered and
+    /// captured. The video, sized as with `Recorder::for_window`, is finalized when the window is
+    /// closed or `frame_cb` returns `Control::Stop`. The first error, from the initialization,
+    /// a snapshot or the finalization, stops the recording and is returned; the video is then
+    /// finalized on a best-effort basis.
+    pub fn record_session<P, F>(&self, window: &mut Window, path: &P, mut frame_cb: F)
+                                -> Result<(), RecorderError>
+        where P: ?Sized + AsRef<OsStr>,
+              F: FnMut(&mut Window, usize) -> Control {
+        let mut recorder = self.build_for_window(window, path);
+        let mut frame    = 0;
+
+        while frame_cb(window, frame) == Control::Continue && window.render() {
+            let _ = try!(recorder.snap(window));
+            frame = frame + 1;
+        }
+
+        recorder.finalize()
+    }
+}
@@ -0,0 +1,67 @@
+//! Pull-based recording, from any producer of images.
+
+use {Recorder, RecorderError};
+
+/// A producer of RGB images, pulled one at a time by `Recorder::drive`.
+pub trait FrameSource {
+    /// The next image, with its width and height, or `None` once the source is exhausted.
+    ///
+    /// The image must be tightly packed RGB24, with rows ordered bottom to top, as for
+    /// `Recorder::snap_rgb`.
+    fn next_frame(&mut self) -> Option<(&[u8], usize, usize)>;
+}
+
+/// A `FrameSource` yielding the images of an iterator.
+///
+/// The iterator yields the images with their width and height, e.g. `(Vec<u8>, usize, usize)`.
+pub struct IterSource<I, T> {
+    iter:    I,
+    current: Option<(T, usize, usize)>
+}
+
+impl<I, T> IterSource<I, T>
+    where I: Iterator<Item = (T, usize, usize)>,
+          T: AsRef<[u8]> {
+    /// Creates a source yielding the images of `iter`.
+    pub fn new(iter: I) -> IterSource<I, T> {
+        IterSource {
+            iter:    iter,
+            current: None
+        }
+    }
+}
+
+impl<I, T> FrameSource for IterSource<I, T>
+    where I: Iterator<Item = (T, usize, usize)>,
+          T: AsRef<[u8]> {
+    fn next_frame(&mut self) -> Option<(&[u8], usize, usize)> {
+        // The image is kept until the next call, for the returned slice to borrow it.
+        self.current = self.iter.next();
+
+        match self.current {
+            Some((ref data, width, height)) => Some((data.as_ref(), width, height)),
+            None                            => None
+        }
+    }
+}
+
+impl Recorder {
+    /// Records every image of `source`, then finishes the recording.
+    ///
+    /// This is the pull counterpart of calling `snap_rgb` repeatedly, for producers driven by the
+    /// recorder, e.g. plugins or decoders. The first error stops the recording; it is otherwise
+    /// finalized as with `finalize`. With `set_frame_limit`, no more images are pulled once the
+    /// limit is reached, so the source may be endless.
+    pub fn drive<S: FrameSource>(mut self, mut source: S) -> Result<(), RecorderError> {
+        while !self.is_complete() {
+            let (data, width, height) = match source.next_frame() {
+                Some(frame) => frame,
+                None        => break
+            };
+
+            let _ = try!(self.snap_rgb(data, width, height));
+        }
+
+        self.finalize()
+    }
+}
@@ -0,0 +1,77 @@
+//! Recording of side-by-side stereoscopic 3D videos.
+
+use libc::c_int;
+use avutil::{self, AVFrame};
+use {Recorder, RecorderError, SnapOutcome};
+
+// XXX: those should be defined by the bindings.
+const AV_STEREO3D_SIDEBYSIDE: c_int = 1;
+
+#[repr(C)]
+struct AVStereo3D {
+    kind:  c_int,
+    flags: c_int
+}
+
+extern "C" {
+    fn av_stereo3d_create_side_data(frame: *mut AVFrame) -> *mut AVStereo3D;
+}
+
+impl Recorder {
+    /// Adds a stereoscopic image to the current video, from the views of the left and right eyes.
+    ///
+    /// Both views must be tightly packed RGB24 images of `width * height` pixels, with rows ordered
+    /// bottom to top as for `snap_rgb`. They are placed side by side, the left view first, so the
+    /// video is twice as wide as each view: give the recorder a width of `2 * width`, or 0 to
+    /// derive it from the first image. Enable `RecorderBuilder::stereo_side_by_side` as well, for
+    /// players to show the video in 3D. Errors are reported as for `snap`; views of the wrong
+    /// length fail with `RecorderError::IncompleteFrame`, and empty views with
+    /// `RecorderError::InvalidDimensions`.
+    pub fn snap_stereo(&mut self, left: &[u8], right: &[u8], width: usize, height: usize)
+                       -> Result<SnapOutcome, RecorderError> {
+        let len = width * height * 3;
+
+        if left.len() != len {
+            return Err(RecorderError::IncompleteFrame(len, left.len()));
+        }
+
+        if right.len() != len {
+            return Err(RecorderError::IncompleteFrame(len, right.len()));
+        }
+
+        if width == 0 || height == 0 {
+            return Err(RecorderError::InvalidDimensions(width, height));
+        }
+
+        if self.skips_capture() {
+            return Ok(SnapOutcome::default());
+        }
+
+        let row_len = width * 3;
+
+        self.tmp_frame_buf.clear();
+
+        for (l, r) in left.chunks(row_len).zip(right.chunks(row_len)) {
+            self.tmp_frame_buf.extend(l.iter().cloned());
+            self.tmp_frame_buf.extend(r.iter().cloned());
+        }
+
+        self.encode_tmp_frame(2 * width, height, avutil::PIX_FMT_RGB24, 3)
+    }
+}
+
+/// Tags a frame as holding side-by-side views, for encoders that signal it in the bitstream,
+/// e.g. libx264 with a frame packing SEI message.
+///
+/// The side data stays attached to the frame, so this is done once for the reused frame.
+pub unsafe fn tag_side_by_side(frame: *mut AVFrame) -> Result<(), RecorderError> {
+    let stereo = av_stereo3d_create_side_data(frame);
+
+    if stereo.is_null() {
+        return Err(RecorderError::FrameAllocation);
+    }
+
+    (*stereo).kind = AV_STEREO3D_SIDEBYSIDE;
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+//! Video recording with encoding offloaded to a background thread.
+
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+#[cfg(feature = "kiss3d")]
+use kiss3d::window::Window;
+use log::warn;
+use {Recorder, RecorderBuilder, RecorderError};
+
+/// What to do with a new frame when the queue of frames waiting to be encoded is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait until the encoder catches up. No frame is lost, but the render loop may stall.
+    Block,
+    /// Drop the new frame. The render loop never stalls, but the video may stutter.
+    DropNewest,
+    /// Drop the oldest queued frame to make room for the new one. The render loop never stalls,
+    /// and the video keeps up with the most recent frames.
+    DropOldest
+}
+
+// A raw RGB24 image, with its width and height.
+type Frame = (Vec<u8>, usize, usize);
+
+/// A video recorder encoding frames on a background thread.
+///
+/// Snapshots only grab the pixels and queue them; the scaling, encoding and writing happen on a
+/// dedicated worker thread so they do not stall the render loop.
+pub struct ThreadedRecorder {
+    sender:         Option<SyncSender<Frame>>,
+    // Shared with the worker so that the oldest frame can be discarded under `DropOldest`.
+    receiver:       Arc<Mutex<Receiver<Frame>>>,
+    worker:         Option<JoinHandle<Result<(), RecorderError>>>,
+    backpressure:   Backpressure,
+    dropped_frames: usize
+}
+
+impl Recorder {
+    /// Creates a new video recorder encoding on a background thread.
+    ///
+    /// # Arguments:
+    /// * `path`         - path to the output file.
+    /// * `width`        - width of the recorded video.
+    /// * `height`       - height of the recorded video.
+    /// * `queue_len`    - the maximum number of frames waiting to be encoded.
+    /// * `backpressure` - what to do when `queue_len` frames are already waiting.
+    pub fn new_threaded<P: ?Sized + AsRef<OsStr>>(path:         &P,
+                                                  width:        usize,
+                                                  height:       usize,
+                                                  queue_len:    usize,
+                                                  backpressure: Backpressure)
+                                                  -> ThreadedRecorder {
+        RecorderBuilder::new().build_threaded(path, width, height, queue_len, backpressure)
+    }
+}
+
+impl RecorderBuilder {
+    /// Creates a video recorder encoding on a background thread.
+    ///
+    /// See `Recorder::new_threaded` for the meaning of the arguments.
+    pub fn build_threaded<P: ?Sized + AsRef<OsStr>>(&self,
+                                                    path:         &P,
+                                                    width:        usize,
+                                                    height:       usize,
+                                                    queue_len:    usize,
+                                                    backpressure: Backpressure)
+                                                    -> ThreadedRecorder {
+        let (sender, receiver) = mpsc::sync_channel::<Frame>(queue_len);
+        let receiver        = Arc::new(Mutex::new(receiver));
+        let worker_receiver = receiver.clone();
+        let builder         = self.clone();
+        let path            = PathBuf::from(path);
+
+        let worker = thread::spawn(move || {
+            // The recorder is built on the worker so that its FFmpeg contexts never leave it.
+            let mut recorder    = builder.build(&path, width, height);
+            let mut first_error = None;
+
+            loop {
+                // The lock is released as soon as a frame is received.
+                let frame = worker_receiver.lock().unwrap().recv();
+
+                let (data, w, h) = match frame {
+                    Ok(frame) => frame,
+                    Err(_)    => break // The channel is closed and drained.
+                };
+
+                if let Err(e) = recorder.snap_rgb(&data[..], w, h) {
+                    if first_error.is_none() {
+                        first_error = Some(e);
+                    }
+                }
+            }
+
+            let finalized = recorder.finalize();
+
+            match first_error {
+                Some(e) => Err(e),
+                None    => finalized
+            }
+        });
+
+        ThreadedRecorder {
+            sender:         Some(sender),
+            receiver:       receiver,
+            worker:         Some(worker),
+            backpressure:   backpressure,
+            dropped_frames: 0
+        }
+    }
+}
+
+impl ThreadedRecorder {
+    /// Captures an image from the window and queues it for encoding.
+    ///
+    /// Depending on the backpressure policy, this either blocks or drops the frame when the queue
+    /// is full. Fails if the worker thread stopped unexpectedly, or with
+    /// `RecorderError::IncompleteFrame` if the window returns a partial image.
+    #[cfg(feature = "kiss3d")]
+    pub fn snap(&mut self, window: &Window) -> Result<(), RecorderError> {
+        let mut data = Vec::new();
+
+        let (width, height) = try!(::snap_window(window, &mut data));
+
+        self.send(data, width, height)
+    }
+
+    /// Queues an RGB image for encoding.
+    ///
+    /// `data` must be tightly packed RGB24, as for `Recorder::snap_rgb`. Fails with
+    /// `RecorderError::IncompleteFrame` otherwise, without queuing anything.
+    pub fn snap_rgb(&mut self, data: &[u8], width: usize, height: usize) -> Result<(), RecorderError> {
+        if data.len() != width * height * 3 {
+            return Err(RecorderError::IncompleteFrame(width * height * 3, data.len()));
+        }
+
+        self.send(data.to_vec(), width, height)
+    }
+
+    /// The number of frames dropped so far because the encoder could not keep up.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames
+    }
+
+    /// Finishes the recording.
+    ///
+    /// This waits for every queued frame to be encoded, then finalizes the video as
+    /// `Recorder::finalize` does. The first error met by the worker thread, if any, is returned.
+    pub fn finalize(mut self) -> Result<(), RecorderError> {
+        self.join()
+    }
+
+    fn send(&mut self, data: Vec<u8>, width: usize, height: usize) -> Result<(), RecorderError> {
+        let sender = self.sender.as_ref().unwrap();
+
+        if self.backpressure == Backpressure::Block {
+            return sender.send((data, width, height)).map_err(|_| RecorderError::WorkerStopped);
+        }
+
+        let frame = match sender.try_send((data, width, height)) {
+            Ok(())                             => return Ok(()),
+            Err(TrySendError::Full(frame))     => frame,
+            Err(TrySendError::Disconnected(_)) => return Err(RecorderError::WorkerStopped)
+        };
+
+        self.dropped_frames = self.dropped_frames + 1;
+
+        warn(&format!("the encoder cannot keep up: dropped a frame ({} so far).", self.dropped_frames));
+
+        if self.backpressure == Backpressure::DropOldest {
+            // Discard the stalest frame. If the worker holds the lock, it is already taking a
+            // frame out of the queue; waiting for the lock could deadlock once the queue is empty.
+            if let Ok(receiver) = self.receiver.try_lock() {
+                let _ = receiver.try_recv();
+            }
+
+            match sender.try_send(frame) {
+                Ok(())                             => { },
+                Err(TrySendError::Full(_))         => { },
+                Err(TrySendError::Disconnected(_)) => return Err(RecorderError::WorkerStopped)
+            }
+        }
+
+        Ok(())
+    }
+
+    fn join(&mut self) -> Result<(), RecorderError> {
+        // Closing the channel lets the worker drain the queue and finalize the video.
+        drop(self.sender.take());
+
+        match self.worker.take() {
+            Some(worker) => match worker.join() {
+                Ok(res) => res,
+                Err(_)  => Err(RecorderError::WorkerStopped)
+            },
+            None => Ok(())
+        }
+    }
+}
+
+impl Drop for ThreadedRecorder {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
@@ -0,0 +1,50 @@
+extern crate kiss3d_recording;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use kiss3d_recording::RecorderBuilder;
+
+#[test]
+fn webm_path_gives_a_vp9_webm_file() {
+    let (width, height) = (320, 240);
+    let path            = env::temp_dir().join("kiss3d_recording_test.webm");
+
+    let mut recorder = RecorderBuilder::new().codec_name("libvpx-vp9")
+                                             .crf(30.0)
+                                             .build(&path, width, height);
+
+    // A moving gradient, generated without any window.
+    let mut data = vec![0u8; width * height * 3];
+
+    for t in 0 .. 30 {
+        for j in 0 .. height {
+            for i in 0 .. width {
+                let k = (j * width + i) * 3;
+
+                data[k]     = ((i + t * 2) % 256) as u8;
+                data[k + 1] = ((j + t) % 256) as u8;
+                data[k + 2] = 128;
+            }
+        }
+
+        let _ = recorder.snap_rgb(&data[..], width, height).unwrap();
+    }
+
+    let info = recorder.stream_info().unwrap();
+
+    assert_eq!(info.codec_name, "libvpx-vp9");
+    assert_eq!(info.format_name, "webm");
+
+    recorder.finalize().unwrap();
+
+    let mut bytes = Vec::new();
+
+    let _ = File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // An EBML header with the "webm" doctype, and a VP9 track.
+    assert_eq!(&bytes[.. 4], &[0x1a, 0x45, 0xdf, 0xa3][..]);
+    assert!(bytes.windows(4).any(|w| w == &b"webm"[..]));
+    assert!(bytes.windows(5).any(|w| w == &b"V_VP9"[..]));
+}